@@ -24,25 +24,32 @@
         cargo = rust;
         rustc = rust;
       };
-    in {
-      devShell = pkgs.mkShell {
+      shell = {
         nativeBuildInputs = [
           rust
           cargo-aoc.defaultPackage.${system}
           pkgs.hyperfine
           pkgs.cargo-flamegraph
-          pkgs.z3
         ];
         RUST_PATH = "${rust}";
         RUST_DOC_PATH = "${rust}/share/doc/rust/html/std/index.html";
         AOC_YEAR = "2023";
-        LIBCLANG_PATH = "${pkgs.llvmPackages.libclang.lib}/lib";
-        LD_LIBRARY_PATH = "${pkgs.z3.lib}/lib";
-        BINDGEN_EXTRA_CLANG_ARGS = let
-          inherit (pkgs) lib stdenv;
-        in
-          "-I${pkgs.z3.dev}/include "
-          + "-isystem ${stdenv.cc.cc}/include/c++/${lib.getVersion stdenv.cc.cc} -isystem ${stdenv.cc.cc}/include/c++/${lib.getVersion stdenv.cc.cc}/${stdenv.hostPlatform.config}";
+      };
+    in {
+      devShells = {
+        default = pkgs.mkShell shell;
+        # Building the `z3` feature (`cargo build --features z3`) needs z3 and clang for bindgen
+        z3 = pkgs.mkShell (shell
+          // {
+            nativeBuildInputs = shell.nativeBuildInputs ++ [pkgs.z3];
+            LIBCLANG_PATH = "${pkgs.llvmPackages.libclang.lib}/lib";
+            LD_LIBRARY_PATH = "${pkgs.z3.lib}/lib";
+            BINDGEN_EXTRA_CLANG_ARGS = let
+              inherit (pkgs) lib stdenv;
+            in
+              "-I${pkgs.z3.dev}/include "
+              + "-isystem ${stdenv.cc.cc}/include/c++/${lib.getVersion stdenv.cc.cc} -isystem ${stdenv.cc.cc}/include/c++/${lib.getVersion stdenv.cc.cc}/${stdenv.hostPlatform.config}";
+          });
       };
 
       defaultPackage = naersk'.buildPackage ./.;
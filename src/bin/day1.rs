@@ -1,81 +1,122 @@
 use std::time::Instant;
 
 use aho_corasick::AhoCorasick;
-use aoc_2023::{load, print_res};
+use aoc_2023::{load_streamable, parse::ParseError, print_res, stream::Records, NoArgs};
 use bstr::{BStr, BString, ByteSlice};
-use color_eyre::eyre;
 
 type Parsed<'a> = Vec<&'a BStr>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
+pub fn parsing(input: &BString) -> Result<Parsed<'_>, ParseError> {
     Ok(input.lines().map(BStr::new).collect())
 }
 
-pub fn part1(input: Parsed) -> color_eyre::Result<()> {
-    let calibration = input
+fn digit_calibration(i: usize, line: &BStr) -> Result<u64, ParseError> {
+    let no_digit = || ParseError::at(line, line, "No digit in line").on_line(i + 1);
+    let first = line
         .iter()
-        .map(|line| {
-            let Some(first) = line.iter().find(|p| p.is_ascii_digit()) else {
-                eyre::bail!("No digit in string");
-            };
+        .find(|p| p.is_ascii_digit())
+        .ok_or_else(no_digit)?;
+    let last = line
+        .iter()
+        .rfind(|p| p.is_ascii_digit())
+        .ok_or_else(no_digit)?;
+
+    let first = first - b'0';
+    let last = last - b'0';
+
+    Ok(first as u64 * 10 + last as u64)
+}
+
+const PATTERNS: &[&[u8]] = &[
+    b"one", b"two", b"three", b"four", b"five", b"six", b"seven", b"eight", b"nine", b"1", b"2",
+    b"3", b"4", b"5", b"6", b"7", b"8", b"9",
+];
 
-            let Some(last) = line.iter().rfind(|p| p.is_ascii_digit()) else {
-                eyre::bail!("No digit in string");
-            };
+fn word_matcher() -> AhoCorasick {
+    AhoCorasick::new(PATTERNS).unwrap()
+}
+
+fn word_calibration(matcher: &AhoCorasick, i: usize, line: &BStr) -> Result<u64, ParseError> {
+    let mut matches = matcher.find_overlapping_iter(line);
+    let first = matches
+        .next()
+        .ok_or_else(|| ParseError::at(line, line, "No digit nor number in line").on_line(i + 1))?;
+    let last = matches.last().unwrap_or(first);
+
+    fn to_num(s: &[u8]) -> u64 {
+        match s {
+            b"one" => 1,
+            b"two" => 2,
+            b"three" => 3,
+            b"four" => 4,
+            b"five" => 5,
+            b"six" => 6,
+            b"seven" => 7,
+            b"eight" => 8,
+            b"nine" => 9,
+            _ => (s[0] - b'0') as u64,
+        }
+    }
 
-            let first = first - b'0';
-            let last = last - b'0';
+    let first = to_num(PATTERNS[first.pattern()]);
+    let last = to_num(PATTERNS[last.pattern()]);
 
-            Ok(first as u64 * 10 + last as u64)
-        })
+    Ok(10 * first + last)
+}
+
+pub fn part1(input: Parsed) -> color_eyre::Result<()> {
+    let calibration = input
+        .iter()
+        .enumerate()
+        .map(|(i, &line)| digit_calibration(i, line))
         .sum::<Result<u64, _>>()?;
 
     print_res!("Calibration is: {calibration}");
     Ok(())
 }
 
-pub fn part2(input: Parsed) {
-    let patterns: &[&[u8]] = &[
-        b"one", b"two", b"three", b"four", b"five", b"six", b"seven", b"eight", b"nine", b"1",
-        b"2", b"3", b"4", b"5", b"6", b"7", b"8", b"9",
-    ];
-
-    let matcher = AhoCorasick::new(patterns).unwrap();
+pub fn part2(input: Parsed) -> color_eyre::Result<()> {
+    let matcher = word_matcher();
 
     let calibration = input
         .iter()
-        .map(|line| {
-            let mut matches = matcher.find_overlapping_iter(line);
-            let first = matches.next().unwrap();
-            let last = matches.last().unwrap_or(first);
-
-            fn to_num(s: &[u8]) -> u64 {
-                match s {
-                    b"one" => 1,
-                    b"two" => 2,
-                    b"three" => 3,
-                    b"four" => 4,
-                    b"five" => 5,
-                    b"six" => 6,
-                    b"seven" => 7,
-                    b"eight" => 8,
-                    b"nine" => 9,
-                    _ => (s[0] - b'0') as u64,
-                }
-            }
-
-            let first = to_num(patterns[first.pattern()]);
-            let last = to_num(patterns[last.pattern()]);
-
-            10 * first + last
-        })
-        .sum::<u64>();
+        .enumerate()
+        .map(|(i, line)| word_calibration(&matcher, i, line))
+        .sum::<Result<u64, _>>()?;
 
     print_res!("Calibration is: {calibration}");
+    Ok(())
+}
+
+pub fn stream_part1(records: Records) -> color_eyre::Result<()> {
+    let calibration: u64 = records.par_sum(|i, line| Ok(digit_calibration(i, line)?))?;
+    print_res!("Calibration is: {calibration}");
+    Ok(())
+}
+
+pub fn stream_part2(records: Records) -> color_eyre::Result<()> {
+    let matcher = word_matcher();
+    let calibration: u64 = records.par_sum(|i, line| Ok(word_calibration(&matcher, i, line)?))?;
+    print_res!("Calibration is: {calibration}");
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_streamable::<NoArgs>()?;
+
+    if context.args.stream {
+        let start = Instant::now();
+        let records = context.records(b'\n')?;
+        if context.part == 1 {
+            stream_part1(records)?;
+        } else {
+            stream_part2(records)?;
+        }
+        let elapsed = humantime::format_duration(start.elapsed());
+
+        println!("  Streaming: {elapsed}");
+        return Ok(());
+    }
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
@@ -85,7 +126,7 @@ pub fn main() -> color_eyre::Result<()> {
     if context.part == 1 {
         part1(parsed)?;
     } else {
-        part2(parsed);
+        part2(parsed)?;
     }
     let elapsed_part = humantime::format_duration(start.elapsed());
 
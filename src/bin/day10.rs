@@ -1,54 +1,89 @@
 use std::{collections::HashSet, time::Instant};
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    load,
+    maze::{Junctions, Point},
+    parse::{self, ParseError},
+    print_res,
+};
 use bstr::{BString, ByteSlice};
-use color_eyre::eyre::eyre;
 use itertools::Itertools;
 
 type Parsed<'a> = ((usize, usize), Vec<BString>);
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    let grid = input.lines().map(BString::from).collect_vec();
-    let (start, _) = grid
+/// Finds the start of the loop, replacing it in the grid by the pipe it stands on
+pub fn parsing(input: &BString) -> Result<Parsed<'_>, ParseError> {
+    let mut grid: Vec<BString> = parse::grid(input, |c| b"|-LJ7F.S".contains(&c).then_some(c))?;
+    let lines = input.lines().collect_vec();
+    let width = grid[0].len();
+    let cell = |(x, y): Point| &lines[y][x..=x];
+    let error = |span, message: &str| ParseError::at(input, span, message);
+
+    let mut starts = grid
         .iter()
         .enumerate()
-        .flat_map(|(y, l)| l.iter().enumerate().map(move |(x, c)| ((x, y), c)))
-        .find(|(_, c)| **c == b'S')
-        .ok_or_else(|| eyre!("No start position"))?;
+        .flat_map(|(y, line)| line.find_iter("S").map(move |x| (x, y)));
+    let start = starts
+        .next()
+        .ok_or_else(|| error(&input[input.len()..], "No start position"))?;
+    if let Some(second) = starts.next() {
+        return Err(error(cell(second), "Second start position"));
+    }
+
+    grid[start.1][start.0] = start_char(start, &grid)
+        .ok_or_else(|| error(cell(start), "Start is not connected to exactly two pipes"))?;
+
+    // Each pipe of the loop must lead to a pipe connected back to it
+    let (mut prev, mut current) = (start, pipe_ends(start, &grid).unwrap().0);
+    while current != start {
+        let connected = current.0 < width
+            && current.1 < grid.len()
+            && pipe_ends(current, &grid).is_some_and(|(a, b)| a == prev || b == prev);
+        if !connected {
+            return Err(error(cell(prev), "Pipe of the loop is not connected"));
+        }
+
+        (prev, current) = (current, next(prev, current, &grid));
+    }
+
     Ok((start, grid))
 }
 
-fn start_char((sx, sy): (usize, usize), grid: &[BString]) -> u8 {
+fn start_char((sx, sy): (usize, usize), grid: &[BString]) -> Option<u8> {
     let top = sy != 0 && [b'|', b'7', b'F'].contains(&grid[sy - 1][sx]);
     let bot = sy != grid.len() - 1 && [b'|', b'J', b'L'].contains(&grid[sy + 1][sx]);
     let left = sx != 0 && [b'-', b'L', b'F'].contains(&grid[sy][sx - 1]);
     let right = sx != grid[0].len() - 1 && [b'-', b'J', b'7'].contains(&grid[sy][sx + 1]);
 
     match (top, bot, left, right) {
-        (true, true, false, false) => b'|',
-        (true, false, true, false) => b'J',
-        (true, false, false, true) => b'L',
-        (false, true, true, false) => b'7',
-        (false, true, false, true) => b'F',
-        (false, false, true, true) => b'-',
-        _ => unreachable!("Should only have two connections"),
+        (true, true, false, false) => Some(b'|'),
+        (true, false, true, false) => Some(b'J'),
+        (true, false, false, true) => Some(b'L'),
+        (false, true, true, false) => Some(b'7'),
+        (false, true, false, true) => Some(b'F'),
+        (false, false, true, true) => Some(b'-'),
+        _ => None,
     }
 }
 
-fn pipe_ends((x, y): (usize, usize), grid: &[BString]) -> ((usize, usize), (usize, usize)) {
+/// Cells connected by the pipe at `(x, y)`, `None` if there is no pipe
+fn pipe_ends((x, y): Point, grid: &[BString]) -> Option<(Point, Point)> {
+    let (up, down) = ((x, y.wrapping_sub(1)), (x, y + 1));
+    let (left, right) = ((x.wrapping_sub(1), y), (x + 1, y));
+
     match grid[y][x] {
-        b'|' => ((x, y - 1), (x, y + 1)),
-        b'-' => ((x - 1, y), (x + 1, y)),
-        b'L' => ((x, y - 1), (x + 1, y)),
-        b'J' => ((x, y - 1), (x - 1, y)),
-        b'7' => ((x, y + 1), (x - 1, y)),
-        b'F' => ((x, y + 1), (x + 1, y)),
-        c => unreachable!("Invalid pipe: {}", c as char),
+        b'|' => Some((up, down)),
+        b'-' => Some((left, right)),
+        b'L' => Some((up, right)),
+        b'J' => Some((up, left)),
+        b'7' => Some((down, left)),
+        b'F' => Some((down, right)),
+        _ => None,
     }
 }
 
 fn next(prev: (usize, usize), cur: (usize, usize), grid: &[BString]) -> (usize, usize) {
-    let (a, b) = pipe_ends(cur, grid);
+    let (a, b) = pipe_ends(cur, grid).expect("The loop is made of pipes");
 
     if a == prev {
         b
@@ -57,31 +92,24 @@ fn next(prev: (usize, usize), cur: (usize, usize), grid: &[BString]) -> (usize,
     }
 }
 
-pub fn part1((start, mut grid): Parsed) {
-    let start_pipe = start_char(start, &grid);
-    grid[start.1][start.0] = start_pipe;
-
-    let mut current = pipe_ends(start, &grid).0;
-    let mut prev = start;
-
-    let mut loop_len = 1;
-
-    while current != start {
-        (prev, current) = (current, next(prev, current, &grid));
-
-        loop_len += 1;
-    }
+pub fn part1((start, grid): Parsed) {
+    // Pipes connect at most two cells, the loop being a corridor from the start back to itself
+    let connects = |from, to| pipe_ends(from, &grid).is_some_and(|(a, b)| a == to || b == to);
+    let junctions = Junctions::new(grid[0].len(), grid.len(), &[start], |from, to| {
+        connects(from, to) && connects(to, from)
+    });
+    let loop_len = *junctions
+        .graph
+        .edge_weight(0, 0)
+        .expect("The start is on a loop");
 
     print_res!("Furthest loop distance: {}", loop_len / 2 + (loop_len % 2));
 }
 
-pub fn part2((start, mut grid): Parsed) {
-    let start_pipe = start_char(start, &grid);
-    grid[start.1][start.0] = start_pipe;
-
+pub fn part2((start, grid): Parsed) {
     let mut edges = Vec::new();
 
-    let mut current = pipe_ends(start, &grid).0;
+    let mut current = pipe_ends(start, &grid).unwrap().0;
     let mut prev = start;
 
     let mut loop_coords = HashSet::new();
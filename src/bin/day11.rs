@@ -1,8 +1,12 @@
 use std::time::Instant;
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    load,
+    parse::{self, ParseError},
+    print_res,
+};
 use bitvec::vec::BitVec;
-use bstr::{BString, ByteSlice};
+use bstr::BString;
 use itertools::Itertools;
 
 #[derive(Debug)]
@@ -27,20 +31,13 @@ impl std::fmt::Display for NebulaGrid {
 
 type Parsed = NebulaGrid;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    input
-        .lines()
-        .map(|line| {
-            line.iter()
-                .map(|&c| match c {
-                    b'#' => Ok(true),
-                    b'.' => Ok(false),
-                    _ => color_eyre::eyre::bail!("Invalid character: {}", c as char),
-                })
-                .collect()
-        })
-        .try_collect()
-        .map(NebulaGrid)
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    parse::grid(input, |c| match c {
+        b'#' => Some(true),
+        b'.' => Some(false),
+        _ => None,
+    })
+    .map(NebulaGrid)
 }
 
 impl NebulaGrid {
@@ -1,12 +1,16 @@
 use std::{collections::HashMap, time::Instant};
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    load_streamable,
+    parse::{self, ParseError},
+    print_res,
+    stream::Records,
+    Threads,
+};
 use bstr::{BString, ByteSlice};
-use color_eyre::eyre::eyre;
-//use indicatif::ParallelProgressIterator;
-use indicatif::ProgressIterator;
+use indicatif::ParallelProgressIterator;
 use itertools::Itertools;
-//use rayon::prelude::*;
+use rayon::prelude::*;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum State {
@@ -38,32 +42,42 @@ impl std::fmt::Display for SpringField {
 
 type Parsed<'a> = Vec<(SpringField, Vec<usize>)>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
+fn parse_row(i: usize, line: &[u8]) -> Result<(SpringField, Vec<usize>), ParseError> {
+    let parse = || {
+        let (springs, ranges) = line.split_once_str(" ").ok_or_else(|| {
+            ParseError::at(line, line, "Missing ' ' between the springs and the ranges")
+        })?;
+
+        let ranges = parse::split(ranges, b",")
+            .map(|n| parse::from_str(line, n))
+            .try_collect()?;
+
+        let springs = springs
+            .iter()
+            .enumerate()
+            .map(|(x, &c)| match c {
+                b'#' => Ok(State::Damaged),
+                b'.' => Ok(State::Operational),
+                b'?' => Ok(State::Unknown),
+                _ => Err(ParseError::at(
+                    line,
+                    &springs[x..=x],
+                    "Invalid spring state",
+                )),
+            })
+            .try_collect()?;
+
+        Ok((SpringField(springs), ranges))
+    };
+
+    parse().map_err(|e: ParseError| e.on_line(i + 1))
+}
+
+pub fn parsing(input: &BString) -> Result<Parsed<'_>, ParseError> {
     input
         .lines()
-        .map(|line| {
-            let line = line.as_bstr();
-            let (springs, ranges) = line
-                .split_once_str(" ")
-                .ok_or_else(|| eyre!("Invalid line: {line}"))?;
-
-            let ranges = std::str::from_utf8(ranges)?
-                .split(',')
-                .map(|n| n.parse())
-                .try_collect()?;
-
-            let springs = springs
-                .iter()
-                .map(|&c| match c {
-                    b'#' => Ok(State::Damaged),
-                    b'.' => Ok(State::Operational),
-                    b'?' => Ok(State::Unknown),
-                    _ => Err(eyre!("Invalid spring state: {}", c as char)),
-                })
-                .try_collect()?;
-
-            Ok((SpringField(springs), ranges))
-        })
+        .enumerate()
+        .map(|(i, line)| parse_row(i, line))
         .collect()
 }
 
@@ -133,7 +147,7 @@ fn possible_arrangements<'a>(
     let mut remaining_ranges = vec![(im::Vector::new(), ranges, im::Vector::new())];
 
     for &run in runs.iter() {
-        let could_fit = (run.len() + 1) / 2;
+        let could_fit = run.len().div_ceil(2);
 
         //println!(" run {} (could fit {could_fit})", SpringField(run.to_vec()));
 
@@ -190,58 +204,92 @@ fn possible_arrangements<'a>(
 }
 
 pub fn part1(input: Parsed) {
-    let mut cache = Cache::new();
-
     let number_of_arrangements: usize = input
-        .iter()
-        .map(|(s, r)| possible_arrangements(&s.0, r, &mut cache))
+        .par_iter()
+        .map(|(s, r)| possible_arrangements(&s.0, r, &mut Cache::new()))
         .sum();
 
     print_res!("Total number of arragengements: {number_of_arrangements}");
 }
 
 #[allow(unstable_name_collisions)]
+fn unfold(s: SpringField, r: Vec<usize>) -> (SpringField, Vec<usize>) {
+    let repeated = SpringField(
+        std::iter::repeat_with(|| s.0.clone())
+            .take(5)
+            .intersperse_with(|| vec![State::Unknown])
+            .flatten()
+            .collect(),
+    );
+    (repeated, r.repeat(5))
+}
+
 pub fn part2(input: Parsed) {
-    let mut cache = Cache::new();
-
-    let inputs = input
-        .into_iter()
-        .map(|(s, r)| {
-            let repeated = SpringField(
-                std::iter::repeat_with(|| s.0.clone())
-                    .take(5)
-                    .intersperse_with(|| vec![State::Unknown])
-                    .flatten()
-                    .collect(),
-            );
-            (repeated, r.repeat(5))
-        })
-        .collect_vec();
+    let inputs = input.into_iter().map(|(s, r)| unfold(s, r)).collect_vec();
 
     let line_count = inputs.len() as u64;
     let number_of_arrangements: usize = inputs
-        .iter()
-        //.into_par_iter()
-        .map(|(s, r)| possible_arrangements(&s.0, r, &mut cache))
+        .par_iter()
+        .map(|(s, r)| possible_arrangements(&s.0, r, &mut Cache::new()))
         .progress_count(line_count)
         .sum();
 
     print_res!("Total number of arragengements: {number_of_arrangements}");
 }
 
+pub fn stream_part1(records: Records) -> color_eyre::Result<()> {
+    let number_of_arrangements: usize = records.par_sum(|i, line| {
+        let (s, r) = parse_row(i, line)?;
+        Ok(possible_arrangements(&s.0, &r, &mut Cache::new()))
+    })?;
+
+    print_res!("Total number of arragengements: {number_of_arrangements}");
+    Ok(())
+}
+
+pub fn stream_part2(records: Records) -> color_eyre::Result<()> {
+    let number_of_arrangements: usize = records.par_sum(|i, line| {
+        let (s, r) = parse_row(i, line)?;
+        let (s, r) = unfold(s, r);
+        Ok(possible_arrangements(&s.0, &r, &mut Cache::new()))
+    })?;
+
+    print_res!("Total number of arragengements: {number_of_arrangements}");
+    Ok(())
+}
+
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_streamable::<Threads>()?;
+    let threads = &context.args.args;
+
+    if context.args.stream {
+        let start = Instant::now();
+        let records = context.records(b'\n')?;
+        threads.install(|| {
+            if context.part == 1 {
+                stream_part1(records)
+            } else {
+                stream_part2(records)
+            }
+        })??;
+        let elapsed = humantime::format_duration(start.elapsed());
+
+        println!("  Streaming: {elapsed}");
+        return Ok(());
+    }
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
     let elapsed = humantime::format_duration(start.elapsed());
 
     let start = Instant::now();
-    if context.part == 1 {
-        part1(parsed);
-    } else {
-        part2(parsed);
-    }
+    threads.install(|| {
+        if context.part == 1 {
+            part1(parsed);
+        } else {
+            part2(parsed);
+        }
+    })?;
     let elapsed_part = humantime::format_duration(start.elapsed());
 
     println!("  Parsing: {elapsed}");
@@ -1,18 +1,39 @@
 use std::time::Instant;
 
-use aoc_2023::{load, print_res};
-use bstr::{BString, ByteSlice};
+use aoc_2023::{
+    load_with,
+    parse::{self, ParseError},
+    print_res,
+};
+use bstr::BString;
+use color_eyre::eyre::{eyre, Context};
 use itertools::Itertools;
 
-type Parsed<'a> = Vec<Pattern>;
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Number of smudges on the mirrors [default: 0 for part 1, 1 for part 2]
+    #[arg(long)]
+    smudges: Option<u32>,
+    /// Print the cells that are smudged
+    #[arg(long)]
+    show_smudges: bool,
+}
+
+type Parsed = Vec<Pattern>;
 
-pub struct Pattern(Vec<Vec<bool>>);
+/// Pattern with its rows and columns as bitmasks, a set bit being a rock
+pub struct Pattern {
+    /// Bit `x` of row `y` is the cell `(x, y)`
+    rows: Vec<u64>,
+    /// Bit `y` of column `x` is the cell `(x, y)`
+    columns: Vec<u64>,
+}
 
 impl std::fmt::Display for Pattern {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for line in &self.0 {
-            for &c in line {
-                write!(f, "{}", if c { '#' } else { '.' })?;
+        for row in &self.rows {
+            for x in 0..self.columns.len() {
+                write!(f, "{}", if row & 1 << x != 0 { '#' } else { '.' })?;
             }
             writeln!(f)?;
         }
@@ -21,185 +42,146 @@ impl std::fmt::Display for Pattern {
     }
 }
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    input
-        .split_str(b"\n\n")
-        .map(|l| {
-            Ok(Pattern(
-                l.lines()
-                    .map(|l| {
-                        l.iter()
-                            .map(|&c| match c {
-                                b'.' => Ok(false),
-                                b'#' => Ok(true),
-                                _ => color_eyre::eyre::bail!("Invalid character: {}", c as char),
-                            })
-                            .try_collect()
-                    })
-                    .try_collect()?,
-            ))
-        })
-        .try_collect()
-}
-
-fn position_iterator(start: usize, len: usize, skip: Option<usize>) -> impl Iterator<Item = usize> {
-    (0..start)
-        .rev()
-        .interleave(start..(len - 1))
-        .filter(move |&s| Some(s + 1) != skip)
-}
+fn parse_pattern(input: &[u8], pattern: &[u8]) -> Result<Pattern, ParseError> {
+    let error = |span, message: String| ParseError::at(input, span, message);
+
+    let grid: Vec<Vec<bool>> = parse::grid_in(input, pattern, |c| match c {
+        b'.' => Some(false),
+        b'#' => Some(true),
+        _ => None,
+    })?;
+    let width = grid[0].len();
+    if width > 64 || grid.len() > 64 {
+        return Err(error(pattern, "Pattern is larger than 64 cells".into()));
+    }
 
-impl Pattern {
-    fn vertical_reflection_at(&self, col: usize) -> bool {
-        let mut offset = 0;
-
-        let get_col = |idx: usize| self.0.iter().map(move |row| row[idx]);
-
-        while col >= offset && col + 1 + offset < self.0[0].len() {
-            //println!("col {col} offset {offset}:");
-
-            let left = get_col(col - offset);
-            let right = get_col(col + 1 + offset);
-
-            if !left
-                .zip_eq(right)
-                // .inspect(|(l, r)| {
-                //     let to_c = |&b| if b { '#' } else { '.' };
-                //     println!("{} {}", to_c(l), to_c(r));
-                // })
-                .all(|(a, b)| a == b)
-            {
-                return false;
+    let mut rows = vec![0; grid.len()];
+    let mut columns = vec![0; width];
+    for (y, line) in grid.into_iter().enumerate() {
+        for (x, rock) in line.into_iter().enumerate() {
+            if rock {
+                rows[y] |= 1 << x;
+                columns[x] |= 1 << y;
             }
-
-            offset += 1;
         }
-
-        true
-    }
-
-    fn vertical_reflection(&self, skip: Option<usize>) -> Option<usize> {
-        //println!("Pattern:\n{self}");
-        let p = &self.0;
-        let len = p[0].len();
-        let start = (len / 2) + (len % 2);
-
-        position_iterator(start, len, skip)
-            .find(|&i| self.vertical_reflection_at(i))
-            .map(|i| i + 1)
     }
 
-    fn horizontal_reflection_at(&self, col: usize) -> bool {
-        let mut offset = 0;
-
-        while col >= offset && col + 1 + offset < self.0.len() {
-            //println!("col {col} offset {offset}:");
+    Ok(Pattern { rows, columns })
+}
 
-            let left = self.0[col - offset].iter();
-            let right = self.0[col + 1 + offset].iter();
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    parse::split(input, b"\n\n")
+        .map(|p| parse_pattern(input, p))
+        .try_collect()
+}
 
-            if !left
-                .zip_eq(right)
-                // .inspect(|(l, r)| {
-                //     let to_c = |&b| if b { '#' } else { '.' };
-                //     println!("{} {}", to_c(l), to_c(r));
-                // })
-                .all(|(a, b)| a == b)
-            {
-                return false;
-            }
+#[derive(Clone, Copy, Debug)]
+enum Reflection {
+    /// Mirror after this many columns
+    Vertical(usize),
+    /// Mirror after this many rows
+    Horizontal(usize),
+}
 
-            offset += 1;
+impl Reflection {
+    fn score(self) -> usize {
+        match self {
+            Reflection::Vertical(columns) => columns,
+            Reflection::Horizontal(rows) => rows * 100,
         }
-
-        true
-    }
-
-    fn horizontal_reflection(&self, skip: Option<usize>) -> Option<usize> {
-        //println!("Pattern:\n{self}");
-        let p = &self.0;
-        let len = p.len();
-        let start = (len / 2) + (len % 2);
-
-        position_iterator(start, len, skip)
-            .find(|&i| self.horizontal_reflection_at(i))
-            .map(|i| i + 1)
     }
+}
 
-    fn reflection_score(&self) -> usize {
-        //println!("Reflections for:\n{self}");
+/// Pairs of lines mirrored by an axis after the first `axis` lines
+fn mirrored(lines: &[u64], axis: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..axis).rev().zip(axis..lines.len())
+}
 
-        let vertical = self.vertical_reflection(None);
-        let horizontal = self.horizontal_reflection(None);
+/// Axes where the mirrored lines differ by exactly `smudges` cells
+fn axes(lines: &[u64], smudges: u32) -> impl Iterator<Item = usize> + '_ {
+    (1..lines.len()).filter(move |&axis| {
+        mirrored(lines, axis)
+            .map(|(a, b)| (lines[a] ^ lines[b]).count_ones())
+            .sum::<u32>()
+            == smudges
+    })
+}
 
-        match (vertical, horizontal) {
-            (None, None) => {
-                panic!("No reflection found in:\n{self}");
-            }
-            (Some(s), None) => s,
-            (None, Some(s)) => s * 100,
-            (Some(a), Some(b)) => panic!("Ambigous reflection vertical {a}, horizontal {b}"),
+impl Pattern {
+    /// The only reflection where exactly `smudges` cells differ from their mirror image
+    fn reflection(&self, smudges: u32) -> color_eyre::Result<Reflection> {
+        let vertical = axes(&self.columns, smudges).map(Reflection::Vertical);
+        let horizontal = axes(&self.rows, smudges).map(Reflection::Horizontal);
+
+        match vertical.chain(horizontal).at_most_one() {
+            Ok(Some(reflection)) => Ok(reflection),
+            Ok(None) => Err(eyre!("No reflection with {smudges} smudges in:\n{self}")),
+            Err(all) => Err(eyre!(
+                "Ambiguous reflections with {smudges} smudges: {:?}",
+                all.collect_vec()
+            )),
         }
     }
 
-    fn reflection_score_ignore(&self, score: usize) -> Option<usize> {
-        //println!("Reflections for:\n{self}");
-
-        let vertical_skip = if score >= 100 { None } else { Some(score) };
-        let horizontal_skip = if score >= 100 {
-            Some(score / 100)
-        } else {
-            None
+    /// Cells differing from their mirror image, along with the mirror image
+    fn smudges(&self, reflection: Reflection) -> Vec<((usize, usize), (usize, usize))> {
+        let (lines, axis) = match reflection {
+            Reflection::Vertical(axis) => (&self.columns, axis),
+            Reflection::Horizontal(axis) => (&self.rows, axis),
         };
 
-        //dbg!(score, vertical_skip, horizontal_skip);
-
-        let vertical = self.vertical_reflection(vertical_skip);
-        let horizontal = self.horizontal_reflection(horizontal_skip).map(|s| s * 100);
-
-        match (vertical, horizontal) {
-            (None, None) => None,
-            (Some(s), None) | (None, Some(s)) => Some(s),
-            (Some(a), Some(b)) => {
-                panic!("Ambigous reflection vertical {a:?}, horizontal {b:?}")
+        let mut smudges = Vec::new();
+        for (a, b) in mirrored(lines, axis) {
+            let mut differences = lines[a] ^ lines[b];
+            while differences != 0 {
+                let cell = differences.trailing_zeros() as usize;
+                differences &= differences - 1;
+
+                smudges.push(match reflection {
+                    Reflection::Vertical(_) => ((a, cell), (b, cell)),
+                    Reflection::Horizontal(_) => ((cell, a), (cell, b)),
+                });
             }
         }
-    }
-}
 
-pub fn part1(input: Parsed) {
-    let score = input.iter().map(Pattern::reflection_score).sum::<usize>();
-    print_res!("Score: {score}");
+        smudges
+    }
 }
 
-pub fn part2(input: Parsed) {
-    let scores = input.iter().map(Pattern::reflection_score).collect_vec();
-
-    let smudged_score = input
-        .into_iter()
+fn summarize(input: &Parsed, smudges: u32, options: &Options) -> color_eyre::Result<usize> {
+    input
+        .iter()
         .enumerate()
-        .map(|(i, mut p)| {
-            for y in 0..p.0.len() {
-                for x in 0..p.0[0].len() {
-                    p.0[y][x] ^= true;
-                    let smudged = p.reflection_score_ignore(scores[i]);
-                    p.0[y][x] ^= true;
-
-                    if let Some(smudged) = smudged {
-                        return smudged;
-                    }
+        .map(|(i, pattern)| {
+            let reflection = pattern
+                .reflection(smudges)
+                .wrap_err_with(|| format!("In pattern {i}"))?;
+
+            if options.show_smudges {
+                for ((x, y), (mx, my)) in pattern.smudges(reflection) {
+                    print_res!("Pattern {i}: smudge at ({x}, {y}) or its mirror ({mx}, {my})");
                 }
             }
 
-            panic!("Did not find a smudge in\n{p}");
+            Ok(reflection.score())
         })
-        .sum::<usize>();
+        .sum()
+}
 
+pub fn part1(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    let score = summarize(&input, options.smudges.unwrap_or(0), options)?;
+    print_res!("Score: {score}");
+    Ok(())
+}
+
+pub fn part2(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    let smudged_score = summarize(&input, options.smudges.unwrap_or(1), options)?;
     print_res!("Smudged score: {smudged_score}");
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_with::<Options>()?;
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
@@ -207,9 +189,9 @@ pub fn main() -> color_eyre::Result<()> {
 
     let start = Instant::now();
     if context.part == 1 {
-        part1(parsed);
+        part1(parsed, &context.args)?;
     } else {
-        part2(parsed);
+        part2(parsed, &context.args)?;
     }
     let elapsed_part = humantime::format_duration(start.elapsed());
 
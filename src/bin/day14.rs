@@ -1,266 +1,310 @@
-use std::{collections::HashMap, time::Instant};
+use std::{str::FromStr, time::Instant};
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    load_with,
+    parse::{self, ParseError},
+    print_res,
+};
 use bstr::{BString, ByteSlice};
+use color_eyre::eyre::{bail, ensure};
 use itertools::Itertools;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Slot {
-    Empty,
-    Rock,
-    Stop,
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Sides the board is tilted towards in a cycle, among N, W, S and E
+    #[arg(long, default_value = "NWSE")]
+    tilts: Tilts,
+    /// Number of cycles to run
+    #[arg(long, default_value_t = 1000000000)]
+    cycles: u64,
+    /// Print the load after each cycle, until the boards repeat
+    #[arg(long)]
+    loads: bool,
+    /// Print the board after this many cycles
+    #[arg(long)]
+    render: Option<u64>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Board(Vec<Vec<Slot>>);
+#[derive(Debug, Clone, Copy)]
+enum Tilt {
+    North,
+    West,
+    South,
+    East,
+}
 
-impl std::fmt::Display for Board {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for line in &self.0 {
-            for e in line {
-                write!(
-                    f,
-                    "{}",
-                    match e {
-                        Slot::Empty => '.',
-                        Slot::Rock => 'O',
-                        Slot::Stop => '#',
-                    }
-                )?;
-            }
-            writeln!(f)?;
+impl Tilt {
+    /// Quarter turns clockwise bringing this side to the north
+    fn turns(self) -> usize {
+        match self {
+            Tilt::North => 0,
+            Tilt::West => 1,
+            Tilt::South => 2,
+            Tilt::East => 3,
         }
-        Ok(())
     }
 }
 
-type Parsed = Board;
-
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    input
-        .lines()
-        .map(|l| {
-            l.iter()
-                .map(|&c| {
-                    Ok(match c {
-                        b'.' => Slot::Empty,
-                        b'O' => Slot::Rock,
-                        b'#' => Slot::Stop,
-                        _ => color_eyre::eyre::bail!("Invalid slot: {}", c as char),
-                    })
+#[derive(Debug, Clone)]
+struct Tilts(Vec<Tilt>);
+
+impl FromStr for Tilts {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ensure!(!s.is_empty(), "Empty tilt sequence");
+        s.chars()
+            .map(|c| {
+                Ok(match c {
+                    'N' => Tilt::North,
+                    'W' => Tilt::West,
+                    'S' => Tilt::South,
+                    'E' => Tilt::East,
+                    _ => bail!("Invalid tilt: {c}"),
                 })
-                .collect()
-        })
-        .try_collect()
-        .map(Board)
+            })
+            .try_collect()
+            .map(Tilts)
+    }
 }
 
-fn empty(Board(shape): &Board) -> Board {
-    Board(vec![vec![Slot::Empty; shape[0].len()]; shape.len()])
+/// Board with its rows as bitsets, bit `x` of row `y` being the cell `(x, y)`.
+///
+/// Rows past the height are kept empty so that the board can be rotated in place.
+#[derive(Debug, Clone)]
+pub struct Board {
+    width: usize,
+    height: usize,
+    rocks: Vec<u128>,
+    /// Cube rocks for each number of quarter turns
+    stops: [Vec<u128>; 4],
+    /// Quarter turns clockwise from the orientation of the input
+    turns: usize,
+    /// Buffer the rocks are rotated into
+    scratch: Vec<u128>,
 }
 
-impl Board {
-    fn load(&self) -> usize {
-        self.0
-            .iter()
-            .enumerate()
-            .map(|(i, r)| (self.0.len() - i) * r.iter().filter(|&&s| s == Slot::Rock).count())
-            .sum()
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        self.turns == other.turns && self.rocks == other.rocks
     }
+}
 
-    // Board `to` should start empty
-    fn fold_column_north_into(&self, idx: usize, Board(to): &mut Board) {
-        let from = &self.0;
-
-        let mut stop_row = 0;
-        let mut rock_count = 0;
-
-        let mut set_range = |start: usize, len: usize, elem| {
-            to[start..start + len]
-                .iter_mut()
-                .for_each(|row| row[idx] = elem)
-        };
-
-        for (i, row) in from.iter().enumerate() {
-            match row[idx] {
-                Slot::Empty => (),
-                Slot::Rock => rock_count += 1,
-                Slot::Stop => {
-                    // Total len = i - stop_row = rock_count + empty_count
-                    //  empty_count = i - stop_row - rock_count
-                    //
-                    //  Example: i = 8, stop_row = 0, rock_count = 4
-                    //  set_range(0, 4)
-                    set_range(stop_row, rock_count, Slot::Rock);
-                    set_range(i, 1, Slot::Stop);
-
-                    stop_row = i + 1;
-                    rock_count = 0;
-                }
-            }
-        }
+impl Eq for Board {}
 
-        if rock_count > 0 {
-            set_range(stop_row, rock_count, Slot::Rock);
+impl std::fmt::Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stops = &self.stops[self.turns];
+        for (rocks, stops) in self.rocks.iter().zip(stops).take(self.height) {
+            for x in 0..self.width {
+                let c = match (rocks & 1 << x != 0, stops & 1 << x != 0) {
+                    (true, _) => 'O',
+                    (_, true) => '#',
+                    _ => '.',
+                };
+                write!(f, "{c}")?;
+            }
+            writeln!(f)?;
         }
+        Ok(())
     }
+}
 
-    fn fold_column_south_into(&self, idx: usize, Board(to): &mut Board) {
-        let from = &self.0;
-
-        let mut stop_row = from.len() - 1;
-        let mut rock_count = 0;
-
-        let mut set_range = |start: usize, len: usize, elem| {
-            to[start + 1 - len..start + 1]
-                .iter_mut()
-                .for_each(|row| row[idx] = elem)
-        };
-
-        for (i, row) in from.iter().enumerate().rev() {
-            match row[idx] {
-                Slot::Empty => (),
-                Slot::Rock => rock_count += 1,
-                Slot::Stop => {
-                    set_range(i, 1, Slot::Stop);
-                    set_range(stop_row, rock_count, Slot::Rock);
-
-                    if i != 0 {
-                        stop_row = i - 1;
-                    }
-                    rock_count = 0;
-                }
-            }
-        }
+type Parsed = Board;
 
-        if rock_count > 0 {
-            set_range(stop_row, rock_count, Slot::Rock);
+/// Rotates the `height` first rows a quarter turn clockwise into `into`
+fn rotate_into(rows: &[u128], height: usize, into: &mut [u128]) {
+    into.fill(0);
+    for (y, &row) in rows[..height].iter().enumerate() {
+        let mut row = row;
+        while row != 0 {
+            let x = row.trailing_zeros() as usize;
+            row &= row - 1;
+            into[x] |= 1 << (height - 1 - y);
         }
     }
+}
 
-    fn fold_column_west_into(&self, idx: usize, Board(to): &mut Board) {
-        let from = &self.0;
-
-        let mut stop_col = 0;
-        let mut rock_count = 0;
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    let error = |span, message: String| ParseError::at(input, span, message);
 
-        let mut set_range = |start: usize, len: usize, elem| {
-            to[idx][start..start + len].fill(elem);
-        };
+    let grid: Vec<Vec<u8>> = parse::grid(input, |c| b".O#".contains(&c).then_some(c))?;
+    let (width, height) = (grid[0].len(), grid.len());
 
-        for (i, col) in from[idx].iter().enumerate() {
-            match col {
-                Slot::Empty => (),
-                Slot::Rock => rock_count += 1,
-                Slot::Stop => {
-                    set_range(stop_col, rock_count, Slot::Rock);
-                    set_range(i, 1, Slot::Stop);
+    let lines = input.lines().collect_vec();
+    if let Some(line) = lines.iter().find(|l| l.len() > 128).or(lines.get(128)) {
+        return Err(error(line, "Board is larger than 128 cells".into()));
+    }
 
-                    stop_col = i + 1;
-                    rock_count = 0;
-                }
+    let size = std::cmp::max(width, height);
+    let mut rocks = vec![0; size];
+    let mut stops = vec![0; size];
+    for (y, line) in grid.into_iter().enumerate() {
+        for (x, c) in line.into_iter().enumerate() {
+            match c {
+                b'O' => rocks[y] |= 1 << x,
+                b'#' => stops[y] |= 1 << x,
+                _ => (),
             }
         }
+    }
 
-        if rock_count > 0 {
-            set_range(stop_col, rock_count, Slot::Rock);
-        }
+    let mut rotations = [stops, vec![0; size], vec![0; size], vec![0; size]];
+    for turns in 1..4 {
+        let (done, next) = rotations.split_at_mut(turns);
+        let from_height = if turns % 2 == 1 { height } else { width };
+        rotate_into(&done[turns - 1], from_height, &mut next[0]);
     }
 
-    fn fold_column_east_into(&self, idx: usize, Board(to): &mut Board) {
-        let from = &self.0;
+    Ok(Board {
+        width,
+        height,
+        rocks,
+        stops: rotations,
+        turns: 0,
+        scratch: vec![0; size],
+    })
+}
 
-        let mut stop_col = from[0].len() - 1;
-        let mut rock_count = 0;
+impl Board {
+    fn load(&self) -> usize {
+        debug_assert_eq!(self.turns, 0, "Load is measured on the north side");
+        self.rocks[..self.height]
+            .iter()
+            .enumerate()
+            .map(|(y, r)| (self.height - y) * r.count_ones() as usize)
+            .sum()
+    }
 
-        let mut set_range =
-            |start: usize, len: usize, elem| to[idx][start + 1 - len..start + 1].fill(elem);
+    fn tilt_north(&mut self) {
+        let stops = &self.stops[self.turns];
+
+        // Rows above `y` are already tilted, rocks go up until something blocks them
+        for y in 1..self.height {
+            let mut moving = std::mem::take(&mut self.rocks[y]);
+            let mut row = y;
+            while moving != 0 && row > 0 {
+                let free = moving & !(self.rocks[row - 1] | stops[row - 1]);
+                self.rocks[row] |= moving & !free;
+                moving = free;
+                row -= 1;
+            }
+            self.rocks[row] |= moving;
+        }
+    }
 
-        for (i, col) in from[idx].iter().enumerate().rev() {
-            match col {
-                Slot::Empty => (),
-                Slot::Rock => rock_count += 1,
-                Slot::Stop => {
-                    set_range(stop_col, rock_count, Slot::Rock);
-                    set_range(i, 1, Slot::Stop);
+    /// Rotates the board a quarter turn clockwise, the west side becoming the north side
+    fn rotate(&mut self) {
+        rotate_into(&self.rocks, self.height, &mut self.scratch);
+        std::mem::swap(&mut self.rocks, &mut self.scratch);
+        std::mem::swap(&mut self.width, &mut self.height);
+        self.turns = (self.turns + 1) % 4;
+    }
 
-                    if i != 0 {
-                        stop_col = i - 1;
-                    }
-                    rock_count = 0;
-                }
+    /// Tilts the board towards each side in turn, ending in the orientation of the input
+    fn cycle(&mut self, tilts: &Tilts) {
+        for &tilt in &tilts.0 {
+            while self.turns != tilt.turns() {
+                self.rotate();
             }
+            self.tilt_north();
         }
 
-        if rock_count > 0 {
-            set_range(stop_col, rock_count, Slot::Rock);
+        while self.turns != 0 {
+            self.rotate();
         }
     }
 
-    fn cycle(&mut self) {
-        let mut next = empty(self);
-        for idx in 0..self.0[0].len() {
-            self.fold_column_north_into(idx, &mut next);
-        }
-        *self = next;
+    /// Copies the rocks of a board with the same cube rocks
+    fn copy_from(&mut self, other: &Board) {
+        self.rocks.copy_from_slice(&other.rocks);
+        (self.width, self.height, self.turns) = (other.width, other.height, other.turns);
+    }
+}
 
-        let mut next = empty(self);
-        for idx in 0..self.0.len() {
-            self.fold_column_west_into(idx, &mut next);
-        }
-        *self = next;
+pub fn part1(mut input: Parsed) {
+    input.tilt_north();
 
-        let mut next = empty(self);
-        for idx in 0..self.0[0].len() {
-            self.fold_column_south_into(idx, &mut next);
-        }
-        *self = next;
+    print_res!("Load is {}", input.load());
+}
 
-        let mut next = empty(self);
-        for idx in 0..self.0.len() {
-            self.fold_column_east_into(idx, &mut next);
+/// Cycles before the boards start repeating, and the period they repeat with.
+///
+/// Uses Brent's algorithm, only keeping two boards around.
+fn find_period(input: &Board, tilts: &Tilts) -> (u64, u64) {
+    let mut tortoise = input.clone();
+    let mut hare = input.clone();
+    hare.cycle(tilts);
+
+    let (mut power, mut period) = (1, 1);
+    while tortoise != hare {
+        if power == period {
+            tortoise.copy_from(&hare);
+            power *= 2;
+            period = 0;
         }
-        *self = next;
+        hare.cycle(tilts);
+        period += 1;
     }
-}
 
-pub fn part1(input: Parsed) {
-    let mut rolled = empty(&input);
+    tortoise.copy_from(input);
+    hare.copy_from(input);
+    for _ in 0..period {
+        hare.cycle(tilts);
+    }
 
-    for idx in 0..input.0[0].len() {
-        input.fold_column_north_into(idx, &mut rolled);
+    let mut offset = 0;
+    while tortoise != hare {
+        tortoise.cycle(tilts);
+        hare.cycle(tilts);
+        offset += 1;
     }
 
-    print_res!("Load is {}", rolled.load());
+    (offset, period)
 }
 
-pub fn part2(mut input: Parsed) {
-    let mut cycles = HashMap::new();
-    let mut count = 0;
+/// Board after `cycles`, skipping the repetitions
+fn after(input: &Board, tilts: &Tilts, cycles: u64, (offset, period): (u64, u64)) -> Board {
+    let cycles = match cycles > offset {
+        true => offset + (cycles - offset) % period,
+        false => cycles,
+    };
 
-    while !cycles.contains_key(&input) {
-        cycles.insert(input.clone(), count);
-        input.cycle();
-        count += 1;
+    let mut board = input.clone();
+    for _ in 0..cycles {
+        board.cycle(tilts);
     }
+    board
+}
 
-    let last = cycles.get(&input).unwrap();
-    let cycle_len = count - last;
+pub fn part2(input: Parsed, options: &Options) {
+    let tilts = &options.tilts;
+    let (offset, period) = find_period(&input, tilts);
+    print_res!("Boards repeat every {period} cycles after {offset} cycles");
 
-    let todo = (1000000000 - count) % cycle_len;
+    if options.loads {
+        let mut board = input.clone();
+        for cycle in 1..=offset + period {
+            board.cycle(tilts);
+            print_res!("Load after {cycle} cycles: {}", board.load());
+        }
+    }
 
-    for _ in 0..todo {
-        input.cycle();
+    if let Some(cycles) = options.render {
+        let board = after(&input, tilts, cycles, (offset, period));
+        print_res!("Board after {cycles} cycles:\n{board}");
     }
 
+    let cycles = options.cycles;
+    let board = after(&input, tilts, cycles, (offset, period));
     print_res!(
-        "Load after 1000000000 cycles (period: {cycle_len}): {}",
-        input.load()
+        "Load after {cycles} cycles (period: {period}): {}",
+        board.load()
     );
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_with::<Options>()?;
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
@@ -270,7 +314,7 @@ pub fn main() -> color_eyre::Result<()> {
     if context.part == 1 {
         part1(parsed);
     } else {
-        part2(parsed);
+        part2(parsed, &context.args);
     }
     let elapsed_part = humantime::format_duration(start.elapsed());
 
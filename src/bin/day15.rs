@@ -1,17 +1,59 @@
 use std::time::Instant;
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    load_streamable,
+    parse::{self, ParseError},
+    print_res,
+    stream::Records,
+    NoArgs,
+};
 use bstr::{BStr, BString, ByteSlice};
+use color_eyre::eyre::Context;
 use indexmap::IndexMap;
 
-type Parsed<'a> = Vec<&'a BStr>;
+/// Step of the initialization sequence, hashed whole in part 1
+#[derive(Debug, Clone, Copy)]
+pub struct Step<'a> {
+    text: &'a BStr,
+    label: &'a BStr,
+    operation: Operation,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Remove,
+    Set(u8),
+}
+
+type Parsed<'a> = Vec<Step<'a>>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    Ok(input
+/// Parses `step`, a slice of `input`
+fn parse_step<'a>(input: &[u8], step: &'a [u8]) -> Result<Step<'a>, ParseError> {
+    let (label, operation) = if let Some(label) = step.strip_suffix(b"-") {
+        (label, Operation::Remove)
+    } else if let Some((label, focal)) = step.split_once_str("=") {
+        (label, Operation::Set(parse::from_str(input, focal)?))
+    } else {
+        return Err(ParseError::at(
+            input,
+            step,
+            "Malformed lens, expected '-' or '='",
+        ));
+    };
+
+    Ok(Step {
+        text: step.as_bstr(),
+        label: label.as_bstr(),
+        operation,
+    })
+}
+
+pub fn parsing(input: &BString) -> Result<Parsed<'_>, ParseError> {
+    input
         .trim()
         .split(|&c| c == b',')
-        .map(|h| h.as_bstr())
-        .collect())
+        .map(|step| parse_step(input, step))
+        .collect()
 }
 
 fn ascii_hash(s: &BStr) -> u8 {
@@ -29,30 +71,26 @@ fn ascii_hash(s: &BStr) -> u8 {
 pub fn part1(input: Parsed) {
     let hash_sum = input
         .iter()
-        .copied()
-        .map(ascii_hash)
+        .map(|step| ascii_hash(step.text))
         .map(u64::from)
         .sum::<u64>();
     print_res!("Hash sum: {hash_sum}");
 }
 
-pub fn part2(input: Parsed) {
-    let mut boxes = vec![IndexMap::<&BStr, u8>::new(); 256];
-
-    for lens in &input {
-        if let Some(label) = lens.strip_suffix(b"-") {
-            let hash = ascii_hash(label.into());
-            boxes[hash as usize].shift_remove(&label.as_bstr());
-        } else if let Some((label, focal)) = lens.split_once_str("=") {
-            let hash = ascii_hash(label.into());
-            let focal = std::str::from_utf8(focal).unwrap().parse().unwrap();
-            *boxes[hash as usize].entry(label.into()).or_default() = focal;
-        } else {
-            panic!("Malformed lens: {lens}");
+type Boxes = Vec<IndexMap<BString, u8>>;
+
+fn apply(boxes: &mut Boxes, step: Step) {
+    let lenses = &mut boxes[ascii_hash(step.label) as usize];
+    match step.operation {
+        Operation::Remove => {
+            lenses.shift_remove(step.label);
         }
+        Operation::Set(focal) => *lenses.entry(step.label.into()).or_default() = focal,
     }
+}
 
-    let total_power = boxes
+fn focusing_power(boxes: &Boxes) -> usize {
+    boxes
         .iter()
         .enumerate()
         .flat_map(|(i, b)| {
@@ -60,13 +98,58 @@ pub fn part2(input: Parsed) {
                 .enumerate()
                 .map(move |(l, (_, &f))| (i + 1) * (l + 1) * (f as usize))
         })
-        .sum::<usize>();
+        .sum::<usize>()
+}
+
+pub fn part2(input: Parsed) {
+    let mut boxes = vec![IndexMap::new(); 256];
+
+    for step in input {
+        apply(&mut boxes, step);
+    }
 
+    let total_power = focusing_power(&boxes);
     print_res!("Total focussing power: {total_power}");
 }
 
+pub fn stream_part1(records: Records) -> color_eyre::Result<()> {
+    let hash_sum: u64 = records.par_sum(|i, step| {
+        let step = parse_step(step, step).wrap_err_with(|| format!("In step {}", i + 1))?;
+        Ok(ascii_hash(step.text) as u64)
+    })?;
+    print_res!("Hash sum: {hash_sum}");
+    Ok(())
+}
+
+pub fn stream_part2(records: Records) -> color_eyre::Result<()> {
+    let mut boxes = vec![IndexMap::new(); 256];
+    records.try_for_each(|i, step| {
+        let step = parse_step(step, step).wrap_err_with(|| format!("In step {}", i + 1))?;
+        apply(&mut boxes, step);
+        Ok(())
+    })?;
+
+    let total_power = focusing_power(&boxes);
+    print_res!("Total focussing power: {total_power}");
+    Ok(())
+}
+
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_streamable::<NoArgs>()?;
+
+    if context.args.stream {
+        let start = Instant::now();
+        let records = context.records(b',')?;
+        if context.part == 1 {
+            stream_part1(records)?;
+        } else {
+            stream_part2(records)?;
+        }
+        let elapsed = humantime::format_duration(start.elapsed());
+
+        println!("  Streaming: {elapsed}");
+        return Ok(());
+    }
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
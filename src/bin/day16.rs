@@ -1,12 +1,22 @@
-use std::{collections::HashSet, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    load_with,
+    parse::{self, ParseError},
+    print_res, Threads,
+};
 use bstr::{BStr, BString, ByteSlice};
 use itertools::Itertools;
+use petgraph::{algo::tarjan_scc, prelude::*};
+use rayon::prelude::*;
 
 type Parsed<'a> = Vec<&'a BStr>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
+pub fn parsing(input: &BString) -> Result<Parsed<'_>, ParseError> {
+    parse::grid::<_, ()>(input, |c| b"./\\|-".contains(&c).then_some(()))?;
     Ok(input.lines().map(ByteSlice::as_bstr).collect_vec())
 }
 
@@ -34,104 +44,191 @@ impl Beam {
             Direction::Down => self.y += 1,
         }
     }
+}
+
+/// Set of cells of the grid
+#[derive(Debug, Clone)]
+struct Cells(Vec<u64>);
+
+impl Cells {
+    fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    fn insert(&mut self, cell: usize) {
+        self.0[cell / 64] |= 1 << (cell % 64);
+    }
+
+    fn union_with(&mut self, other: &Cells) {
+        self.0.iter_mut().zip(&other.0).for_each(|(a, b)| *a |= b);
+    }
+
+    /// Number of cells in the union of the two sets
+    fn union_len(&self, other: &Cells) -> usize {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| (a | b).count_ones() as usize)
+            .sum()
+    }
+}
+
+/// Follows a beam until it is split, returning the cells it energises and the splitter it stops
+/// on. The beam may also leave the grid or loop without being split
+fn follow(mut beam: Beam, grid: &[&BStr]) -> (Cells, Option<(usize, usize)>) {
+    let (width, height) = (grid[0].len(), grid.len());
+    let mut cells = Cells::new(width * height);
+    let mut seen = HashSet::new();
 
-    fn passes_through(&self, grid: &[&BStr]) -> HashSet<(isize, isize)> {
-        let mut stack = vec![*self];
+    while (0..width as isize).contains(&beam.x)
+        && (0..height as isize).contains(&beam.y)
+        && seen.insert(beam)
+    {
+        let (x, y) = (beam.x as usize, beam.y as usize);
+        cells.insert(y * width + x);
 
-        let mut seen = HashSet::new();
+        match (grid[y][x], beam.direction) {
+            (b'-', Direction::Up | Direction::Down)
+            | (b'|', Direction::Left | Direction::Right) => return (cells, Some((x, y))),
+            (b'.' | b'-' | b'|', _) => (),
+            (b'/', direction) => {
+                beam.direction = match direction {
+                    Direction::Left => Direction::Down,
+                    Direction::Right => Direction::Up,
+                    Direction::Up => Direction::Right,
+                    Direction::Down => Direction::Left,
+                };
+            }
+            (b'\\', direction) => {
+                beam.direction = match direction {
+                    Direction::Left => Direction::Up,
+                    Direction::Right => Direction::Down,
+                    Direction::Up => Direction::Left,
+                    Direction::Down => Direction::Right,
+                };
+            }
+            _ => unreachable!("Cells are checked while parsing"),
+        }
+
+        beam.step_forwards();
+    }
+
+    (cells, None)
+}
+
+/// Splitters reached by the beams, each energising the cells reached before the next splits.
+///
+/// Splitters lighting each other form strongly connected components, which all light the same
+/// cells: the cells energised from a splitter are computed once for its component.
+struct BeamGraph<'a> {
+    grid: &'a [&'a BStr],
+    /// Component of each splitter, by node
+    components: Vec<usize>,
+    /// Cells energised from each component
+    energised: Vec<Cells>,
+    nodes: HashMap<(usize, usize), NodeIndex>,
+}
 
-        while let Some(mut beam) = stack.pop() {
-            if !(0..(grid[0].len() as isize)).contains(&beam.x)
-                || !(0..(grid.len() as isize)).contains(&beam.y)
-                || seen.contains(&beam)
-            {
+impl<'a> BeamGraph<'a> {
+    fn new(grid: &'a [&'a BStr], entries: &[Beam]) -> Self {
+        let mut graph = Graph::new();
+        let mut nodes = HashMap::new();
+
+        let mut stack = entries
+            .iter()
+            .filter_map(|&entry| follow(entry, grid).1)
+            .collect_vec();
+
+        while let Some((x, y)) = stack.pop() {
+            if nodes.contains_key(&(x, y)) {
                 continue;
             }
 
-            seen.insert(beam);
-            match grid[beam.y as usize][beam.x as usize] {
-                b'.' => (),
-                b'-' => match beam.direction {
-                    Direction::Up | Direction::Down => {
-                        let mut left = Beam {
-                            direction: Direction::Left,
-                            ..beam
-                        };
-                        left.step_forwards();
-                        stack.push(left);
-
-                        let mut right = Beam {
-                            direction: Direction::Right,
-                            ..beam
-                        };
-                        right.step_forwards();
-                        stack.push(right);
-
-                        continue;
-                    }
-                    _ => (),
-                },
-                b'|' => match beam.direction {
-                    Direction::Right | Direction::Left => {
-                        let mut up = Beam {
-                            direction: Direction::Up,
-                            ..beam
-                        };
-                        up.step_forwards();
-                        stack.push(up);
-
-                        let mut down = Beam {
-                            direction: Direction::Down,
-                            ..beam
-                        };
-                        down.step_forwards();
-                        stack.push(down);
-
-                        continue;
+            // Both beams start from the splitter, so that it is energised
+            let directions = match grid[y][x] {
+                b'-' => [Direction::Left, Direction::Right],
+                _ => [Direction::Up, Direction::Down],
+            };
+
+            let mut cells = Cells::new(grid[0].len() * grid.len());
+            let mut splits = Vec::new();
+            for direction in directions {
+                let beam = Beam {
+                    x: x as isize,
+                    y: y as isize,
+                    direction,
+                };
+                let (beam_cells, split) = follow(beam, grid);
+                cells.union_with(&beam_cells);
+                splits.extend(split);
+            }
+
+            nodes.insert((x, y), graph.add_node((cells, splits.clone())));
+            stack.extend(splits);
+        }
+
+        for node in graph.node_indices() {
+            for split in graph[node].1.clone() {
+                graph.add_edge(node, nodes[&split], ());
+            }
+        }
+
+        // Components come in reverse topological order, the ones they light being already done
+        let mut components = vec![0; graph.node_count()];
+        let mut energised: Vec<Cells> = Vec::new();
+        for (i, component) in tarjan_scc(&graph).into_iter().enumerate() {
+            let mut cells = Cells::new(grid[0].len() * grid.len());
+            for &node in &component {
+                components[node.index()] = i;
+                cells.union_with(&graph[node].0);
+            }
+
+            for &node in &component {
+                for next in graph.neighbors(node) {
+                    let next = components[next.index()];
+                    if next != i {
+                        cells.union_with(&energised[next]);
                     }
-                    _ => (),
-                },
-                b'/' => {
-                    beam.direction = match beam.direction {
-                        Direction::Left => Direction::Down,
-                        Direction::Right => Direction::Up,
-                        Direction::Up => Direction::Right,
-                        Direction::Down => Direction::Left,
-                    };
-                }
-                b'\\' => {
-                    beam.direction = match beam.direction {
-                        Direction::Left => Direction::Up,
-                        Direction::Right => Direction::Down,
-                        Direction::Up => Direction::Left,
-                        Direction::Down => Direction::Right,
-                    };
                 }
-                _ => unreachable!("Invalid input"),
             }
 
-            beam.step_forwards();
+            energised.push(cells);
+        }
 
-            stack.push(beam);
+        Self {
+            grid,
+            components,
+            energised,
+            nodes,
         }
+    }
 
-        seen.iter().map(|b| (b.x, b.y)).collect()
+    /// Number of cells energised by a beam entering the grid
+    fn energised(&self, entry: Beam) -> usize {
+        let (cells, split) = follow(entry, self.grid);
+        match split {
+            Some(split) => {
+                let component = self.components[self.nodes[&split].index()];
+                cells.union_len(&self.energised[component])
+            }
+            None => cells.union_len(&cells),
+        }
     }
 }
 
 pub fn part1(input: Parsed) {
-    let coords = Beam {
+    let entry = Beam {
         x: 0,
         y: 0,
         direction: Direction::Right,
-    }
-    .passes_through(&input);
+    };
+    let energised = BeamGraph::new(&input, &[entry]).energised(entry);
 
-    print_res!("Energisted count: {}", coords.len());
+    print_res!("Energisted count: {}", energised);
 }
 
 pub fn part2(input: Parsed) {
-    let max = (0..input[0].len())
+    let entries = (0..input[0].len())
         .flat_map(|x| {
             [
                 Beam {
@@ -160,7 +257,12 @@ pub fn part2(input: Parsed) {
                 },
             ]
         }))
-        .map(|b| b.passes_through(&input).len())
+        .collect_vec();
+
+    let graph = BeamGraph::new(&input, &entries);
+    let max = entries
+        .into_par_iter()
+        .map(|entry| graph.energised(entry))
         .max()
         .unwrap();
 
@@ -168,18 +270,20 @@ pub fn part2(input: Parsed) {
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_with::<Threads>()?;
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
     let elapsed = humantime::format_duration(start.elapsed());
 
     let start = Instant::now();
-    if context.part == 1 {
-        part1(parsed);
-    } else {
-        part2(parsed);
-    }
+    context.args.install(|| {
+        if context.part == 1 {
+            part1(parsed);
+        } else {
+            part2(parsed);
+        }
+    })?;
     let elapsed_part = humantime::format_duration(start.elapsed());
 
     println!("  Parsing: {elapsed}");
@@ -3,23 +3,17 @@ use std::{
     time::Instant,
 };
 
-use aoc_2023::{load, print_res};
-use bstr::{BString, ByteSlice};
+use aoc_2023::{
+    load,
+    parse::{self, ParseError},
+    print_res,
+};
+use bstr::BString;
 
 type Parsed = Vec<Vec<u8>>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    input
-        .lines()
-        .map(|line| {
-            line.iter()
-                .map(|&d| match d {
-                    b'0'..=b'9' => Ok(d - b'0'),
-                    _ => Err(color_eyre::eyre::eyre!("Invalid digit: {}", d as char)),
-                })
-                .collect()
-        })
-        .collect()
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    parse::grid(input, |d| d.is_ascii_digit().then(|| d - b'0'))
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
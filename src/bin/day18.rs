@@ -1,8 +1,11 @@
 use std::{collections::HashSet, time::Instant};
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    load,
+    parse::{self, ParseError},
+    print_res,
+};
 use bstr::BString;
-use color_eyre::eyre::{self, eyre};
 use itertools::Itertools;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -14,42 +17,82 @@ enum Direction {
 }
 
 #[derive(Debug)]
-pub struct Step<'a> {
+pub struct Step {
     direction: Direction,
     amount: i64,
-    color: &'a str,
+    /// Step hidden in the color, dug in part 2
+    color_step: (i64, Direction),
 }
 
-type Parsed<'a> = Vec<Step<'a>>;
-
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    std::str::from_utf8(input)?
-        .lines()
-        .map(|step| {
-            let (dir, rest) = step
-                .split_once(' ')
-                .ok_or_else(|| eyre!("Missing ' ' in {step}"))?;
-
-            let (amount, color) = rest
-                .split_once(' ')
-                .ok_or_else(|| eyre!("Missing 2nd ' ' in {step}"))?;
-
-            Ok(Step {
-                direction: match dir {
-                    "R" => Direction::Right,
-                    "U" => Direction::Up,
-                    "D" => Direction::Down,
-                    "L" => Direction::Left,
-                    _ => eyre::bail!("Invalid direction: {dir}"),
-                },
-                amount: amount.parse()?,
-                color: color
-                    .trim_matches(&['(', ')'] as &[_])
-                    .strip_prefix('#')
-                    .ok_or_else(|| eyre!("Malformed color: {color}"))?,
-            })
-        })
-        .collect()
+type Parsed = Vec<Step>;
+
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    let error = |span: &str, message: &str| ParseError::at(input, span.as_bytes(), message);
+    let text = parse::utf8(input)?;
+
+    let mut steps = Vec::new();
+    // Directions of each step, with where they are written in the input
+    let mut directions = Vec::new();
+    for line in text.lines() {
+        let (dir, rest) = line
+            .split_once(' ')
+            .ok_or_else(|| error(line, "Missing ' ' after the direction"))?;
+        let (amount, color) = rest
+            .split_once(' ')
+            .ok_or_else(|| error(rest, "Missing ' ' after the amount"))?;
+
+        let direction = match dir {
+            "R" => Direction::Right,
+            "U" => Direction::Up,
+            "D" => Direction::Down,
+            "L" => Direction::Left,
+            _ => return Err(error(dir, "Invalid direction, expected R, U, D or L")),
+        };
+
+        let (distance, color_dir) = color
+            .strip_prefix("(#")
+            .and_then(|c| c.strip_suffix(')'))
+            .filter(|c| c.len() == 6 && c.is_char_boundary(5))
+            .ok_or_else(|| error(color, "Malformed color, expected '(#rrggbb)'"))?
+            .split_at(5);
+        let color_direction = match color_dir {
+            "0" => Direction::Right,
+            "1" => Direction::Down,
+            "2" => Direction::Left,
+            "3" => Direction::Up,
+            _ => return Err(error(color_dir, "Invalid direction, expected 0, 1, 2 or 3")),
+        };
+
+        steps.push(Step {
+            direction,
+            amount: parse::from_str(input, amount.as_bytes())?,
+            color_step: (
+                i64::from_str_radix(distance, 16)
+                    .map_err(|e| ParseError::at(input, distance.as_bytes(), e))?,
+                color_direction,
+            ),
+        });
+        directions.push([(direction, dir), (color_direction, color_dir)]);
+    }
+
+    if steps.is_empty() {
+        return Err(error(text, "No steps"));
+    }
+
+    // The trench goes back and forth between horizontal & vertical steps, looping back to the start
+    for (i, step) in directions.iter().enumerate() {
+        let previous = &directions[(i + directions.len() - 1) % directions.len()];
+        for (&(previous, _), &(direction, span)) in previous.iter().zip(step) {
+            if horizontal(previous) == horizontal(direction) {
+                return Err(error(
+                    span,
+                    "Direction is not perpendicular to the previous one",
+                ));
+            }
+        }
+    }
+
+    Ok(steps)
 }
 
 #[allow(unused)]
@@ -186,45 +229,18 @@ where
     total as usize
 }
 
-fn assert_perpendicular(a: Direction, b: Direction) {
-    match (a, b) {
-        (Direction::Left | Direction::Right, Direction::Left | Direction::Right)
-        | (Direction::Up | Direction::Down, Direction::Up | Direction::Down) => {
-            panic!("Not perpendicular: {a:?} & {b:?}")
-        }
-        _ => (),
-    }
+fn horizontal(direction: Direction) -> bool {
+    matches!(direction, Direction::Left | Direction::Right)
 }
 
 pub fn part1(input: Parsed) {
-    assert_perpendicular(input[0].direction, input.last().unwrap().direction);
-
     let count = trenched_count(input.iter().map(|s| (s.amount, s.direction)));
 
     print_res!("Size of pool: {count}");
 }
 
 pub fn part2(input: Parsed) {
-    let steps = input
-        .iter()
-        .map(|s| {
-            let (dist, dir) = (&s.color[0..5], &s.color[5..]);
-            (
-                i64::from_str_radix(dist, 16).unwrap(),
-                match dir {
-                    "0" => Direction::Right,
-                    "1" => Direction::Down,
-                    "2" => Direction::Left,
-                    "3" => Direction::Up,
-                    _ => unreachable!(),
-                },
-            )
-        })
-        .collect_vec();
-
-    assert_perpendicular(steps[0].1, steps.last().unwrap().1);
-
-    let count = trenched_count(steps.iter().copied());
+    let count = trenched_count(input.iter().map(|s| s.color_step));
 
     print_res!("Size of pool: {count}");
 }
@@ -1,10 +1,26 @@
 use std::{collections::HashMap, time::Instant};
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    interval::{Interval, IntervalBox},
+    load_with,
+    parse::{self, ParseError},
+    print_res,
+};
 use bstr::BString;
+use color_eyre::eyre::ensure;
 use enum_map::{Enum, EnumMap};
 use itertools::Itertools;
 
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Smallest value a part rating can take
+    #[arg(long, default_value_t = 1)]
+    min_rating: u32,
+    /// Largest value a part rating can take
+    #[arg(long, default_value_t = 4000)]
+    max_rating: u32,
+}
+
 #[derive(Enum, Debug, Clone, Copy)]
 pub enum Spec {
     Cool,
@@ -13,23 +29,92 @@ pub enum Spec {
     Shiny,
 }
 
+impl std::fmt::Display for Spec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let c = match self {
+            Spec::Cool => 'x',
+            Spec::Musical => 'm',
+            Spec::Aerodynamic => 'a',
+            Spec::Shiny => 's',
+        };
+        write!(f, "{c}")
+    }
+}
+
+type WorkflowId = u16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Accept,
+    Reject,
+    Workflow(WorkflowId),
+}
+
 #[derive(Debug, Clone, Copy)]
-pub enum Condition<'a> {
-    Less(Spec, u16, &'a str),
-    Greater(Spec, u16, &'a str),
-    Jump(&'a str),
+pub enum Condition<T> {
+    Less(Spec, u32, T),
+    Greater(Spec, u32, T),
+    Jump(T),
+}
+
+impl<T> Condition<T> {
+    /// Splits the range into the values satisfying the condition and the others
+    fn split(&self, range: Interval<u32>) -> (Interval<u32>, Interval<u32>) {
+        match *self {
+            Condition::Less(_, v, _) => range.split_at(v),
+            Condition::Greater(_, v, _) => {
+                let (rest, ok) = range.split_at(v.saturating_add(1));
+                (ok, rest)
+            }
+            Condition::Jump(_) => (range, Interval::empty()),
+        }
+    }
+
+    /// Returns the result of the condition if it is the same for every value in the range
+    fn constant(&self, range: Interval<u32>) -> Option<bool> {
+        match self.split(range) {
+            (_, rest) if rest.is_empty() => Some(true),
+            (ok, _) if ok.is_empty() => Some(false),
+            _ => None,
+        }
+    }
+
+    fn map_target<U>(self, f: impl FnOnce(T) -> U) -> Condition<U> {
+        match self {
+            Condition::Less(spec, v, to) => Condition::Less(spec, v, f(to)),
+            Condition::Greater(spec, v, to) => Condition::Greater(spec, v, f(to)),
+            Condition::Jump(to) => Condition::Jump(f(to)),
+        }
+    }
+}
+
+/// Condition of a workflow, with its text in the input
+type Rule<'a, T> = (&'a str, Condition<T>);
+
+type Part = EnumMap<Spec, u32>;
+
+#[derive(Debug)]
+pub struct Workflows<'a> {
+    input: &'a [u8],
+    labels: Vec<&'a str>,
+    rules: Vec<Vec<Rule<'a, Target>>>,
+    start: WorkflowId,
+    ratings: Interval<u32>,
 }
 
-type Part = EnumMap<Spec, u16>;
-type Workflow<'a> = HashMap<&'a str, Vec<Condition<'a>>>;
-type Parsed<'a> = (Workflow<'a>, Vec<Part>);
+type Parsed = (Compiled, Vec<Part>);
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    let input = std::str::from_utf8(input)?;
+pub fn parsing(input: &BString, ratings: Interval<u32>) -> Result<Parsed, ParseError> {
+    let error = |span: &str, message: &str| ParseError::at(input, span.as_bytes(), message);
+    let number = |n: &str| parse::from_str::<u32>(input, n.as_bytes());
+    let text = parse::utf8(input)?;
 
-    let (workflows, parts) = input
-        .split_once("\n\n")
-        .ok_or_else(|| color_eyre::eyre::eyre!("Missing section separator"))?;
+    let (workflows, parts) = text.split_once("\n\n").ok_or_else(|| {
+        error(
+            text,
+            "Missing empty line between the workflows and the parts",
+        )
+    })?;
 
     let parse_spec = |s| {
         Ok(match s {
@@ -37,195 +122,321 @@ pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
             "m" => Spec::Musical,
             "a" => Spec::Aerodynamic,
             "s" => Spec::Shiny,
-            _ => color_eyre::eyre::bail!("Invalid spec: {s}"),
+            _ => return Err(error(s, "Invalid spec, expected x, m, a or s")),
         })
     };
 
-    let workflows = workflows
+    let mut warnings = Vec::new();
+
+    let workflow_lines: Vec<(&str, Vec<Rule<&str>>)> = workflows
         .lines()
         .map(|l| {
             let (label, conditions) = l
                 .split_once('{')
-                .ok_or_else(|| color_eyre::eyre::eyre!("Missing workflow separator in {l}"))?;
+                .ok_or_else(|| error(l, "Missing '{' after the workflow name"))?;
 
             let conditions = conditions
                 .strip_suffix('}')
-                .ok_or_else(|| {
-                    color_eyre::eyre::eyre!("Conditions do not end with '}}' in {conditions}",)
-                })?
+                .ok_or_else(|| error(conditions, "Conditions do not end with '}'"))?
                 .split(',')
-                .map(|cond| match cond.split_once(':') {
+                .map(|rule| match rule.split_once(':') {
                     Some((cond, to)) => {
-                        let (spec, value) = cond.split_once(['<', '>']).ok_or_else(|| {
-                            color_eyre::eyre::eyre!("Missing comparaison in {cond}")
-                        })?;
+                        let (spec, value) = cond
+                            .split_once(['<', '>'])
+                            .ok_or_else(|| error(cond, "Missing comparison"))?;
 
-                        let side = if cond.contains('<') {
-                            Condition::Less
+                        let condition = if cond.contains('<') {
+                            Condition::Less(parse_spec(spec)?, number(value)?, to)
                         } else {
-                            Condition::Greater
+                            Condition::Greater(parse_spec(spec)?, number(value)?, to)
                         };
 
-                        Ok::<_, color_eyre::Report>(side(parse_spec(spec)?, value.parse()?, to))
+                        if let Some(always) = condition.constant(ratings) {
+                            warnings.push(error(
+                                cond,
+                                &format!(
+                                    "Comparison {cond} is always {always} for ratings in {}",
+                                    show_ratings(ratings)
+                                ),
+                            ));
+                        }
+
+                        Ok::<_, ParseError>((rule, condition))
                     }
-                    None => Ok(Condition::Jump(cond)),
+                    None => Ok((rule, Condition::Jump(rule))),
                 })
                 .try_collect()?;
 
-            Ok::<_, color_eyre::Report>((label, conditions))
+            Ok::<_, ParseError>((label, conditions))
         })
         .try_collect()?;
 
+    let mut errors = Vec::new();
+
+    let mut ids = HashMap::new();
+    for (id, &(label, _)) in workflow_lines.iter().enumerate() {
+        let id = WorkflowId::try_from(id).map_err(|_| {
+            let max = WorkflowId::MAX as usize + 1;
+            error(
+                label,
+                &format!("Too many workflows, at most {max} are supported"),
+            )
+        })?;
+
+        if let Some(first) = ids.insert(label, id) {
+            errors.push(error(
+                label,
+                &format!(
+                    "Workflow {label} is already defined on line {}",
+                    first as usize + 1
+                ),
+            ));
+        }
+    }
+
+    let (labels, rules) = workflow_lines
+        .into_iter()
+        .map(|(label, conditions)| {
+            let conditions = conditions
+                .into_iter()
+                .map(|(rule, c)| {
+                    let c = c.map_target(|to| match to {
+                        "A" => Target::Accept,
+                        "R" => Target::Reject,
+                        _ => match ids.get(to) {
+                            Some(&id) => Target::Workflow(id),
+                            None => {
+                                errors.push(error(to, &format!("Workflow {to} is not defined")));
+                                Target::Reject
+                            }
+                        },
+                    });
+                    (rule, c)
+                })
+                .collect();
+
+            (label, conditions)
+        })
+        .unzip();
+
+    parse::all(errors)?;
+
+    let workflows = Workflows {
+        input,
+        labels,
+        rules,
+        start: *ids
+            .get("in")
+            .ok_or_else(|| error(&workflows[workflows.len()..], "Missing 'in' workflow"))?,
+        ratings,
+    };
+
     let parts = parts
         .lines()
         .map(|p| {
-            p.strip_suffix('}')
-                .ok_or_else(|| color_eyre::eyre::eyre!("Missing '}}' in {p}"))?
+            let part: Part = p
+                .strip_suffix('}')
+                .ok_or_else(|| error(p, "Part does not end with '}'"))?
                 .strip_prefix('{')
-                .ok_or_else(|| color_eyre::eyre::eyre!("Missing '{{' in {p}"))?
+                .ok_or_else(|| error(p, "Part does not start with '{'"))?
                 .split(',')
                 .map(|spec| {
                     let (name, value) = spec
                         .split_once('=')
-                        .ok_or_else(|| color_eyre::eyre::eyre!("Missing '=' in {spec}"))?;
+                        .ok_or_else(|| error(spec, "Missing '=' in the rating"))?;
+
+                    let v = number(value)?;
+                    if !ratings.contains(v) {
+                        warnings.push(error(
+                            spec,
+                            &format!("Rating {spec} is outside of {}", show_ratings(ratings)),
+                        ))
+                    }
 
-                    Ok::<_, color_eyre::Report>((parse_spec(name)?, value.parse()?))
+                    Ok::<_, ParseError>((parse_spec(name)?, v))
                 })
-                .try_collect()
+                .try_collect()?;
+
+            Ok::<_, ParseError>(part)
         })
         .try_collect()?;
 
-    Ok((workflows, parts))
-}
+    let mut compiled = workflows.compile()?;
+    warnings.append(&mut compiled.warnings);
+    warnings.sort_by_key(|w| (w.line, w.column));
+    compiled.warnings = warnings;
 
-fn run_part(workflows: &Workflow, part: &Part) -> bool {
-    let mut current = "in";
+    Ok((compiled, parts))
+}
 
-    loop {
-        if current == "R" {
-            return false;
-        } else if current == "A" {
-            return true;
-        }
+fn show_ratings(ratings: Interval<u32>) -> String {
+    format!("{}..={}", ratings.start, ratings.end - 1)
+}
 
-        let workflow = &workflows[&current];
+type SpecRanges = IntervalBox<u32, 4>;
 
-        for &cond in workflow {
-            match cond {
-                Condition::Less(spec, v, to) => {
-                    if part[spec] < v {
-                        current = to;
-                        break;
-                    }
-                }
-                Condition::Greater(spec, v, to) => {
-                    if part[spec] > v {
-                        current = to;
-                        break;
-                    }
-                }
-                Condition::Jump(c) => {
-                    current = c;
-                    break;
-                }
-            }
-        }
-    }
+/// Workflows lowered to the disjoint ranges of parts they accept
+#[derive(Debug)]
+pub struct Compiled {
+    accepted: Vec<SpecRanges>,
+    warnings: Vec<ParseError>,
 }
 
-pub fn part1((workflows, parts): Parsed) {
-    let total_value = parts
-        .iter()
-        .filter(|&p| run_part(&workflows, p))
-        .map(|p| p.values().map(|&v| v as u64).sum::<u64>())
-        .sum::<u64>();
-    print_res!("Total value is: {total_value}");
+/// State accumulated while walking the workflows from `in`
+struct Lowering {
+    path: Vec<WorkflowId>,
+    entered: Vec<bool>,
+    triggered: Vec<Vec<bool>>,
+    accepted: Vec<SpecRanges>,
+    loops: Vec<ParseError>,
 }
 
-#[derive(Debug, Clone, Copy)]
-struct SpecRange {
-    min: u16,
-    max: u16,
-}
+impl Workflows<'_> {
+    fn compile(&self) -> Result<Compiled, ParseError> {
+        let mut state = Lowering {
+            path: Vec::new(),
+            entered: vec![false; self.rules.len()],
+            triggered: self.rules.iter().map(|w| vec![false; w.len()]).collect(),
+            accepted: Vec::new(),
+            loops: Vec::new(),
+        };
+
+        self.lower(self.start, IntervalBox([self.ratings; 4]), &mut state);
+
+        parse::all(state.loops)?;
+
+        let mut warnings = Vec::new();
+        for (w, rules) in state.triggered.iter().enumerate() {
+            let label = self.labels[w];
+
+            if !state.entered[w] {
+                warnings.push(self.error(label, format!("Workflow {label} is never reached")));
+                continue;
+            }
 
-impl SpecRange {
-    fn empty(&self) -> bool {
-        self.min > self.max
+            for rule in rules.iter().positions(|&t| !t) {
+                warnings.push(self.error(
+                    self.rules[w][rule].0,
+                    format!(
+                        "Rule {} of workflow {label} is shadowed by earlier rules",
+                        rule + 1
+                    ),
+                ));
+            }
+        }
+
+        Ok(Compiled {
+            accepted: state.accepted,
+            warnings,
+        })
     }
-}
 
-impl std::default::Default for SpecRange {
-    fn default() -> Self {
-        Self { min: 1, max: 4000 }
+    fn error(&self, span: &str, message: String) -> ParseError {
+        ParseError::at(self.input, span.as_bytes(), message)
     }
-}
 
-pub fn part2((workflows, _): Parsed) {
-    let mut possibilities = 0;
+    fn lower(&self, workflow: WorkflowId, mut r: SpecRanges, state: &mut Lowering) {
+        if let Some(start) = state.path.iter().position(|&w| w == workflow) {
+            let from = *state.path.last().unwrap();
+            let error = self.error(
+                self.labels[from as usize],
+                format!(
+                    "Workflows loop forever: {}",
+                    state.path[start..]
+                        .iter()
+                        .chain(std::iter::once(&workflow))
+                        .map(|&w| self.labels[w as usize])
+                        .join(" -> ")
+                ),
+            );
+
+            if !state.loops.contains(&error) {
+                state.loops.push(error);
+            }
+            return;
+        }
 
-    let complete_range: EnumMap<Spec, SpecRange> = Default::default();
+        state.path.push(workflow);
+        state.entered[workflow as usize] = true;
 
-    let mut ranges = vec![("in", complete_range)];
+        for (i, &(_, condition)) in self.rules[workflow as usize].iter().enumerate() {
+            let (spec, to) = match condition {
+                Condition::Less(spec, _, to) | Condition::Greater(spec, _, to) => (spec, to),
+                // Jumps take the whole range whatever the spec
+                Condition::Jump(to) => (Spec::Cool, to),
+            };
 
-    while let Some((workflow, mut r)) = ranges.pop() {
-        if workflow == "R" {
-            continue;
-        } else if workflow == "A" {
-            possibilities += r
-                .values()
-                .map(|r| (r.max - r.min + 1) as u64)
-                .product::<u64>();
-            continue;
-        }
+            let (ok, rest) = condition.split(r[spec as usize]);
 
-        let workflow = &workflows[&workflow];
+            if !ok.is_empty() {
+                let mut ok_range = r;
+                ok_range[spec as usize] = ok;
 
-        for &condition in workflow {
-            match condition {
-                Condition::Less(spec, amount, to) => {
-                    let mut ok = r;
+                state.triggered[workflow as usize][i] = true;
+                self.follow(to, ok_range, state);
+            }
 
-                    ok[spec].max = amount - 1;
+            r[spec as usize] = rest;
+            if rest.is_empty() {
+                break;
+            }
+        }
 
-                    if !ok[spec].empty() {
-                        ranges.push((to, ok));
-                    }
+        state.path.pop();
+    }
 
-                    r[spec].min = amount;
-                    if r[spec].empty() {
-                        break;
-                    }
-                }
-                Condition::Greater(spec, amount, to) => {
-                    let mut ok = r;
+    fn follow(&self, to: Target, r: SpecRanges, state: &mut Lowering) {
+        match to {
+            Target::Accept => state.accepted.push(r),
+            Target::Reject => (),
+            Target::Workflow(w) => self.lower(w, r, state),
+        }
+    }
+}
 
-                    ok[spec].min = amount + 1;
+impl Compiled {
+    fn accepts(&self, part: &Part) -> bool {
+        self.accepted.iter().any(|r| r.contains(part.as_array()))
+    }
 
-                    if !ok[spec].empty() {
-                        ranges.push((to, ok));
-                    }
+    fn possibilities(&self) -> u64 {
+        self.accepted.iter().map(|r| r.volume::<u64>()).sum()
+    }
 
-                    r[spec].max = amount;
-                    if r[spec].empty() {
-                        break;
-                    }
-                }
-                Condition::Jump(to) => {
-                    ranges.push((to, r));
-                    break;
-                }
-            }
+    fn report(&self) {
+        for warning in &self.warnings {
+            eprintln!("Warning: {warning}");
         }
     }
+}
+
+pub fn part1((compiled, parts): Parsed) {
+    compiled.report();
+
+    let total_value = parts
+        .iter()
+        .filter(|&p| compiled.accepts(p))
+        .map(|p| p.values().map(|&v| v as u64).sum::<u64>())
+        .sum::<u64>();
+    print_res!("Total value is: {total_value}");
+}
+
+pub fn part2((compiled, _): Parsed) {
+    compiled.report();
 
-    print_res!("Total number of possibilites: {possibilities}");
+    print_res!("Total number of possibilites: {}", compiled.possibilities());
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_with::<Options>()?;
+    let ratings = Interval::new(
+        context.args.min_rating,
+        context.args.max_rating.saturating_add(1),
+    );
+    ensure!(!ratings.is_empty(), "Rating range is empty");
 
     let start = Instant::now();
-    let parsed = parsing(&context.input)?;
+    let parsed = parsing(&context.input, ratings)?;
     let elapsed = humantime::format_duration(start.elapsed());
 
     let start = Instant::now();
@@ -1,8 +1,14 @@
 use std::{str::FromStr, time::Instant};
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    load_streamable,
+    parse::{self, ParseError},
+    print_res,
+    stream::Records,
+    NoArgs,
+};
 use bstr::{BString, ByteSlice};
-use color_eyre::eyre::{self, eyre};
+use color_eyre::eyre;
 use enum_map::{Enum, EnumMap};
 
 #[derive(Debug, Enum, Clone, Copy)]
@@ -25,80 +31,127 @@ impl FromStr for Color {
     }
 }
 
-type Parsed = Vec<Vec<EnumMap<Color, usize>>>;
+type Game = Vec<EnumMap<Color, usize>>;
+
+type Parsed = Vec<Game>;
+
+fn parse_game(i: usize, line: &[u8]) -> Result<Game, ParseError> {
+    let error = |span: &str, message| ParseError::at(line, span.as_bytes(), message);
+    let number = |span: &str| parse::from_str(line, span.as_bytes());
+
+    let parse = || {
+        let game = parse::utf8(line)?;
+        let (prefix, game) = game
+            .split_once(':')
+            .ok_or_else(|| error(game, "Missing ':' after the game number".into()))?;
+
+        let id = prefix
+            .strip_prefix("Game ")
+            .ok_or_else(|| error(prefix, "Game not starting with 'Game'".into()))?
+            .trim();
+        if number(id)? != i + 1 {
+            return Err(error(
+                id,
+                format!("Game is misnumbered, expected {}", i + 1),
+            ));
+        }
+
+        game.split(';')
+            .map(|draw| {
+                draw.split(',')
+                    .map(|cube| {
+                        let cube = cube.trim();
+                        let (amount, color) = cube.split_once(' ').ok_or_else(|| {
+                            error(cube, "Missing ' ' between amount and color".into())
+                        })?;
+                        let color = color
+                            .parse()
+                            .map_err(|e| ParseError::at(line, color.as_bytes(), e))?;
+
+                        Ok((number(amount)?, color))
+                    })
+                    .try_fold(EnumMap::default(), |mut map, res: Result<_, ParseError>| {
+                        let (amount, color): (usize, Color) = res?;
+                        map[color] += amount;
+                        Ok(map)
+                    })
+            })
+            .collect()
+    };
+
+    parse().map_err(|e| e.on_line(i + 1))
+}
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
     input
         .lines()
         .enumerate()
-        .map(|(i, game)| {
-            let game = std::str::from_utf8(game)?;
-            let Some((prefix, game)) = game.split_once(':') else {
-                eyre::bail!("No ':' in {game}")
-            };
-
-            let prefix: usize = prefix
-                .strip_prefix("Game ")
-                .ok_or_else(|| eyre!("Game {i} not starting with 'Game'"))?
-                .trim()
-                .parse()?;
-            eyre::ensure!(prefix == i + 1, "Game {i} is misnumbered");
-
-            game.split(';')
-                .map(|draw| {
-                    draw.split(',')
-                        .map(|cube| -> color_eyre::Result<_> {
-                            let (amount, color) = cube
-                                .trim()
-                                .split_once(' ')
-                                .ok_or_else(|| eyre!("Malformed cube {cube} in game {i}"))?;
-
-                            Ok((amount.parse::<usize>()?, color.parse()?))
-                        })
-                        .try_fold(EnumMap::default(), |mut map, res| {
-                            let (amount, color) = res?;
-                            map[color] += amount;
-                            Ok(map)
-                        })
-                })
-                .collect()
-        })
+        .map(|(i, game)| parse_game(i, game))
         .collect()
 }
 
+fn possible(game: &Game) -> bool {
+    game.iter()
+        .all(|draw| draw[Color::Red] <= 12 && draw[Color::Green] <= 13 && draw[Color::Blue] <= 14)
+}
+
+fn power(game: &Game) -> usize {
+    let min_cubes = game.iter().fold(EnumMap::default(), |mut map, draw| {
+        for color in [Color::Red, Color::Blue, Color::Green] {
+            map[color] = std::cmp::max(map[color], draw[color]);
+        }
+        map
+    });
+
+    min_cubes.values().product::<usize>()
+}
+
 pub fn part1(input: Parsed) {
     let possible_games: usize = input
         .iter()
         .enumerate()
-        .filter(|(_, draws)| {
-            draws.iter().all(|draw| {
-                draw[Color::Red] <= 12 && draw[Color::Green] <= 13 && draw[Color::Blue] <= 14
-            })
-        })
+        .filter(|(_, draws)| possible(draws))
         .map(|(id, _)| id + 1)
         .sum();
     print_res!("Sum of possible games: {possible_games}")
 }
 
 pub fn part2(input: Parsed) {
-    let power_sum = input
-        .iter()
-        .map(|game| {
-            let min_cubes = game.iter().fold(EnumMap::default(), |mut map, draw| {
-                for color in [Color::Red, Color::Blue, Color::Green] {
-                    map[color] = std::cmp::max(map[color], draw[color]);
-                }
-                map
-            });
-
-            min_cubes.values().product::<usize>()
-        })
-        .sum::<usize>();
+    let power_sum = input.iter().map(power).sum::<usize>();
+    print_res!("Sum of game powers: {power_sum}");
+}
+
+pub fn stream_part1(records: Records) -> color_eyre::Result<()> {
+    let possible_games: usize = records.par_sum(|i, game| {
+        let game = parse_game(i, game)?;
+        Ok(if possible(&game) { i + 1 } else { 0 })
+    })?;
+    print_res!("Sum of possible games: {possible_games}");
+    Ok(())
+}
+
+pub fn stream_part2(records: Records) -> color_eyre::Result<()> {
+    let power_sum: usize = records.par_sum(|i, game| Ok(power(&parse_game(i, game)?)))?;
     print_res!("Sum of game powers: {power_sum}");
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_streamable::<NoArgs>()?;
+
+    if context.args.stream {
+        let start = Instant::now();
+        let records = context.records(b'\n')?;
+        if context.part == 1 {
+            stream_part1(records)?;
+        } else {
+            stream_part2(records)?;
+        }
+        let elapsed = humantime::format_duration(start.elapsed());
+
+        println!("  Streaming: {elapsed}");
+        return Ok(());
+    }
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
@@ -1,102 +1,188 @@
-use std::time::Instant;
-
-use aoc_2023::{load, print_res};
-use bstr::{BString, ByteSlice};
-use color_eyre::eyre::ensure;
+use std::{collections::VecDeque, time::Instant};
+
+use aoc_2023::{
+    load,
+    parse::{self, ParseError},
+    print_res,
+};
+use bitvec::vec::BitVec;
+use bstr::{BStr, BString, ByteSlice};
+use color_eyre::eyre::{bail, ensure, eyre};
 use enum_map::{Enum, EnumMap};
 use fxhash::FxHashMap;
 use itertools::Itertools;
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
-pub struct GateName(u16);
-
-impl std::fmt::Debug for GateName {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_tuple("GateName")
-            .field(&format_args!("{self}",))
-            .finish()
-    }
-}
-
-impl std::fmt::Display for GateName {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}{}",
-            (self.0 >> 8) as u8 as char,
-            (self.0 & 255) as u8 as char
-        )
-    }
-}
-
-impl GateName {
-    fn from_bytes(a: &[u8]) -> color_eyre::Result<Self> {
-        color_eyre::eyre::ensure!(a.len() <= 2, "Invalid name: {}", a.as_bstr());
-        Ok(GateName((a[0] as u16) << 8 | a[1] as u16))
-    }
-}
-
-#[derive(Debug)]
-pub struct Broadcaster(Vec<GateName>);
+/// Dense index of a gate, assigned in order of first appearance in the input
+type GateId = u16;
 
 #[derive(Debug, PartialEq, Eq)]
 enum GateKind {
+    /// Gate without a prefix (like `broadcaster`), relaying every pulse it receives
+    Broadcaster,
     FlipFlop,
     Conjunction,
+    /// Gate that is only ever the target of a connection (like `rx`)
+    Output,
+}
+
+/// Connection to a gate, `input` being the bit used in the memory of a conjunction
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    to: GateId,
+    input: u8,
 }
 
 #[derive(Debug)]
 pub struct GateDesc {
     kind: GateKind,
-    to: Vec<GateName>,
+    to: Vec<Edge>,
+    from: Vec<GateId>,
 }
 
-type Parsed<'a> = (Broadcaster, FxHashMap<GateName, GateDesc>);
+#[derive(Debug)]
+pub struct Circuit {
+    names: Vec<BString>,
+    ids: FxHashMap<BString, GateId>,
+    gates: Vec<GateDesc>,
+    /// Gate receiving a low pulse when the button is pressed
+    button: GateId,
+}
+
+impl Circuit {
+    fn id(&self, name: &[u8]) -> Option<GateId> {
+        self.ids.get(name).copied()
+    }
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    let mut brodcaster = None;
-    let mut gates = FxHashMap::default();
+    fn name(&self, id: GateId) -> &BStr {
+        self.names[id as usize].as_bstr()
+    }
+
+    fn predecessors(&self, gate: GateId) -> impl Iterator<Item = GateId> + '_ {
+        self.gates[gate as usize].from.iter().copied()
+    }
+}
+
+type Parsed = Circuit;
+
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    let mut names = Vec::new();
+    let mut ids = FxHashMap::default();
+    let mut kinds = Vec::new();
+    let mut outputs = Vec::new();
+
+    /// Id of the gate called `name`, a slice of `input`
+    fn intern(
+        input: &[u8],
+        name: &[u8],
+        names: &mut Vec<BString>,
+        ids: &mut FxHashMap<BString, GateId>,
+    ) -> Result<GateId, ParseError> {
+        if name.is_empty() {
+            return Err(ParseError::at(input, name, "Empty gate name"));
+        }
+
+        if let Some(&id) = ids.get(name) {
+            return Ok(id);
+        }
+
+        let id = GateId::try_from(names.len()).map_err(|_| {
+            let max = GateId::MAX as usize + 1;
+            ParseError::at(
+                input,
+                name,
+                format!("Too many gates, at most {max} are supported"),
+            )
+        })?;
+        names.push(name.into());
+        ids.insert(name.into(), id);
+
+        Ok(id)
+    }
 
     for line in input.lines() {
-        let (name, outputs) = line
+        let (name, gate_outputs) = line
             .split_once_str(" -> ")
-            .ok_or_else(|| color_eyre::eyre::eyre!("Missing separator in {}", line.as_bstr()))?;
+            .ok_or_else(|| ParseError::at(input, line, "Missing ' -> ' after the gate"))?;
 
-        let outputs = outputs
-            .split_str(", ")
-            .map(GateName::from_bytes)
+        let gate_outputs: Vec<_> = parse::split(gate_outputs, b", ")
+            .map(|n| Ok((intern(input, n, &mut names, &mut ids)?, n)))
             .try_collect()?;
 
-        if name == b"broadcaster" {
-            ensure!(brodcaster.is_none(), "Two broadcasters");
-            brodcaster = Some(Broadcaster(outputs));
-        } else if name[0] == b'%' {
-            let name = GateName::from_bytes(&name[1..])?;
-            gates.insert(
-                name,
-                GateDesc {
-                    kind: GateKind::FlipFlop,
-                    to: outputs,
-                },
-            );
-        } else if name[0] == b'&' {
-            let name = GateName::from_bytes(&name[1..])?;
-            gates.insert(
-                name,
-                GateDesc {
-                    kind: GateKind::Conjunction,
-                    to: outputs,
-                },
-            );
-        } else {
-            color_eyre::eyre::bail!("Invalid gate name: {}", name.as_bstr())
+        let (kind, name) = match name.first() {
+            Some(b'%') => (GateKind::FlipFlop, &name[1..]),
+            Some(b'&') => (GateKind::Conjunction, &name[1..]),
+            _ => (GateKind::Broadcaster, name),
+        };
+
+        let id = intern(input, name, &mut names, &mut ids)?;
+        if kinds.iter().any(|&(g, _)| g == id) {
+            return Err(ParseError::at(input, name, "Gate is defined twice"));
         }
+
+        kinds.push((id, kind));
+        outputs.push((id, gate_outputs));
     }
 
-    Ok((
-        brodcaster.ok_or_else(|| color_eyre::eyre::eyre!("No broadcaster found"))?,
+    // The button is connected to the broadcaster unless the input wires it explicitly
+    let button = match ids.get(b"button".as_slice()) {
+        Some(&button) => button,
+        None => {
+            let broadcaster = *ids.get(b"broadcaster".as_slice()).ok_or_else(|| {
+                ParseError::at_offset(input, input.len(), 0, "No broadcaster or button found")
+            })?;
+            let button = intern(input, b"button", &mut names, &mut ids)?;
+            kinds.push((button, GateKind::Broadcaster));
+            outputs.push((button, vec![(broadcaster, b"broadcaster".as_slice())]));
+            button
+        }
+    };
+
+    let mut gates: Vec<_> = (0..names.len())
+        .map(|_| GateDesc {
+            kind: GateKind::Output,
+            to: Vec::new(),
+            from: Vec::new(),
+        })
+        .collect();
+
+    for (id, kind) in kinds {
+        gates[id as usize].kind = kind;
+    }
+
+    for (id, gate_outputs) in outputs {
+        gates[id as usize].to = gate_outputs
+            .into_iter()
+            .map(|(to, span)| {
+                let target = &mut gates[to as usize];
+                let from = target.from.len();
+                target.from.push(id);
+
+                if target.kind == GateKind::Conjunction && from >= u64::BITS as usize {
+                    return Err(ParseError::at(
+                        input,
+                        span,
+                        format!(
+                            "Conjunction {} has too many inputs, at most {} are supported",
+                            names[to as usize],
+                            u64::BITS
+                        ),
+                    ));
+                }
+
+                Ok(Edge {
+                    to,
+                    input: from as u8,
+                })
+            })
+            .try_collect()?;
+    }
+
+    Ok(Circuit {
+        names,
+        ids,
         gates,
-    ))
+        button,
+    })
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Hash)]
@@ -105,157 +191,96 @@ enum Pulse {
     High,
 }
 
-#[derive(Debug)]
-enum GateState {
-    FlipFlop {
-        state: bool,
-    },
-    Conjunction {
-        from: FxHashMap<GateName, Pulse>,
-        waiting: usize,
-    },
-}
-
 struct Network<'a> {
-    gates: FxHashMap<GateName, GateState>,
-    desc: &'a FxHashMap<GateName, GateDesc>,
-}
-
-fn predecessors(
-    gate_desc: &FxHashMap<GateName, GateDesc>,
-    gate: GateName,
-) -> impl Iterator<Item = GateName> + '_ {
-    gate_desc
-        .iter()
-        .filter_map(move |(src, src_desc)| match src_desc.to.contains(&gate) {
-            true => Some(*src),
-            false => None,
-        })
+    circuit: &'a Circuit,
+    flip_flops: BitVec,
+    /// For each conjunction the inputs that last sent a high pulse, one bit per input
+    memory: Vec<u64>,
+    pulses: VecDeque<(GateId, Edge, Pulse)>,
 }
 
 impl<'a> Network<'a> {
-    fn new(gate_desc: &'a FxHashMap<GateName, GateDesc>, broadcasters: &[GateName]) -> Self {
+    fn new(circuit: &'a Circuit) -> Self {
         Self {
-            desc: gate_desc,
-            gates: gate_desc
-                .iter()
-                .map(|(name, desc)| {
-                    let state = match desc.kind {
-                        GateKind::FlipFlop => GateState::FlipFlop { state: false },
-                        GateKind::Conjunction => {
-                            assert!(
-                                !broadcasters.contains(name),
-                                "Conjunction gates can't have broadcaster as a source"
-                            );
-
-                            let from: FxHashMap<_, _> = predecessors(gate_desc, *name)
-                                .map(|src| (src, Pulse::Low))
-                                .collect();
-                            let waiting = from.len();
-
-                            GateState::Conjunction { from, waiting }
-                        }
-                    };
-                    (*name, state)
-                })
-                .collect(),
+            circuit,
+            flip_flops: BitVec::repeat(false, circuit.gates.len()),
+            memory: vec![0; circuit.gates.len()],
+            pulses: VecDeque::new(),
         }
     }
 
     fn run(
         &mut self,
-        values: &[GateName],
-        mut record: Option<&mut FxHashMap<(GateName, Pulse), usize>>,
+        mut record: Option<&mut FxHashMap<(GateId, Pulse), usize>>,
     ) -> enum_map::EnumMap<Pulse, u64> {
         let mut pulse_count = enum_map::EnumMap::default();
 
-        // println!("button -Low-> broadcaster");
-
-        pulse_count[Pulse::Low] += 1;
+        let button = self.circuit.button;
+        for &edge in &self.circuit.gates[button as usize].to {
+            self.pulses.push_back((button, edge, Pulse::Low));
+        }
 
-        let mut pulses = Vec::new();
+        while let Some((src, edge, len)) = self.pulses.pop_front() {
+            if let Some(r) = record.as_deref_mut() {
+                if let Some(v) = r.get_mut(&(src, len)) {
+                    *v += 1;
+                }
+            }
 
-        for &name in values {
-            pulses.push((None::<GateName>, name, Pulse::Low));
-        }
+            pulse_count[len] += 1;
 
-        while !pulses.is_empty() {
-            let mut new_pulses = Vec::new();
+            let to = edge.to as usize;
+            let gate = &self.circuit.gates[to];
 
-            for &(src, to, len) in &pulses {
-                if let Some(r) = record.as_deref_mut() {
-                    if let Some(src) = src {
-                        if let Some(v) = r.get_mut(&(src, len)) {
-                            *v += 1;
-                        }
-                    }
-                }
-                // println!(
-                //     "{} -{len:?}-> {to}",
-                //     src.as_ref()
-                //         .map(|s| s.to_string())
-                //         .as_deref()
-                //         .unwrap_or("broadcaster")
-                // );
-
-                pulse_count[len] += 1;
-
-                let Some(state) = self.gates.get_mut(&to) else {
-                    continue;
-                };
-
-                let pulse = match state {
-                    GateState::FlipFlop { state } => match len {
-                        Pulse::Low => {
-                            let s = *state;
-                            *state ^= true;
-                            Some(match s {
-                                true => Pulse::Low,
-                                false => Pulse::High,
-                            })
-                        }
-                        Pulse::High => None,
-                    },
-                    GateState::Conjunction { from, waiting } => {
-                        let src = src.unwrap();
-                        let value = from.get_mut(&src).unwrap();
-
-                        if *value != len {
-                            match value {
-                                Pulse::Low => *waiting -= 1,
-                                Pulse::High => *waiting += 1,
-                            }
-                            *value = len;
-                        }
-
-                        Some(match *waiting == 0 {
+            let pulse = match gate.kind {
+                GateKind::Output => None,
+                GateKind::Broadcaster => Some(len),
+                GateKind::FlipFlop => match len {
+                    Pulse::Low => {
+                        let s = self.flip_flops[to];
+                        self.flip_flops.set(to, !s);
+                        Some(match s {
                             true => Pulse::Low,
                             false => Pulse::High,
                         })
                     }
-                };
-
-                if let Some(p) = pulse {
-                    for &next in &self.desc[&to].to {
-                        new_pulses.push((Some(to), next, p))
+                    Pulse::High => None,
+                },
+                GateKind::Conjunction => {
+                    let memory = &mut self.memory[to];
+                    match len {
+                        Pulse::Low => *memory &= !(1 << edge.input),
+                        Pulse::High => *memory |= 1 << edge.input,
                     }
+
+                    let all_high = u64::MAX
+                        .checked_shr(u64::BITS - gate.from.len() as u32)
+                        .unwrap_or(0);
+                    Some(match *memory == all_high {
+                        true => Pulse::Low,
+                        false => Pulse::High,
+                    })
                 }
-            }
+            };
 
-            pulses = new_pulses;
+            if let Some(p) = pulse {
+                for &next in &gate.to {
+                    self.pulses.push_back((edge.to, next, p))
+                }
+            }
         }
 
         pulse_count
     }
 }
 
-pub fn part1((broadcaster, gate_desc): Parsed) {
-    let mut network = Network::new(&gate_desc, &broadcaster.0);
+pub fn part1(circuit: Parsed) {
+    let mut network = Network::new(&circuit);
 
     let mut total_count = EnumMap::<_, u64>::default();
     for _ in 0..1000 {
         network
-            .run(&broadcaster.0, None)
+            .run(None)
             .iter()
             .for_each(|(p, l)| total_count[p] += l);
     }
@@ -274,18 +299,29 @@ fn gcd(mut a: u64, mut b: u64) -> u64 {
     a
 }
 
-fn lcm(a: u64, b: u64) -> u64 {
-    (a * b) / gcd(a, b)
+fn lcm(a: u64, b: u64) -> Option<u64> {
+    (a / gcd(a, b)).checked_mul(b)
 }
 
-pub fn part2((broadcaster, gate_desc): Parsed) {
-    let mut network = Network::new(&gate_desc, &broadcaster.0);
-    let rx = GateName::from_bytes(b"rx").unwrap();
-
-    let (rx_trigger,) = predecessors(&gate_desc, rx).collect_tuple().unwrap();
-    assert!(gate_desc[&rx_trigger].kind == GateKind::Conjunction);
+pub fn part2(circuit: Parsed) -> color_eyre::Result<()> {
+    let mut network = Network::new(&circuit);
+    let rx = circuit.id(b"rx").ok_or_else(|| eyre!("No rx gate"))?;
+
+    let Some((rx_trigger,)) = circuit.predecessors(rx).collect_tuple() else {
+        bail!("rx must have exactly one input")
+    };
+    ensure!(
+        circuit.gates[rx_trigger as usize].kind == GateKind::Conjunction,
+        "The input of rx, {}, must be a conjunction",
+        circuit.name(rx_trigger)
+    );
 
-    let trigger_inputs = predecessors(&gate_desc, rx_trigger).collect_vec();
+    let trigger_inputs = circuit.predecessors(rx_trigger).collect_vec();
+    ensure!(
+        !trigger_inputs.is_empty(),
+        "The input of rx, {}, has no inputs",
+        circuit.name(rx_trigger)
+    );
 
     let mut trigger_iterations: FxHashMap<_, _> =
         trigger_inputs.iter().map(|&g| (g, None)).collect();
@@ -299,26 +335,24 @@ pub fn part2((broadcaster, gate_desc): Parsed) {
 
         i += 1;
 
-        network.run(&broadcaster.0, Some(&mut record));
+        network.run(Some(&mut record));
 
         for ((v, _), c) in record {
-            assert!(c < 2, "{v} triggered twice");
-            if c == 1 {
-                let period = trigger_iterations.get_mut(&v).unwrap();
-                if period.is_none() {
-                    *period = Some(i);
-                }
+            ensure!(c < 2, "{} triggered twice", circuit.name(v));
+            if let (1, Some(period @ None)) = (c, trigger_iterations.get_mut(&v)) {
+                *period = Some(i);
             }
         }
     }
 
     let all_period = trigger_iterations
         .values()
-        .copied()
-        .map(Option::unwrap)
-        .fold(1, lcm);
+        .flatten()
+        .try_fold(1, |all, &period| lcm(all, period))
+        .ok_or_else(|| eyre!("Button count required is too large"))?;
 
     print_res!("Button count required: {all_period}");
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
@@ -332,7 +366,7 @@ pub fn main() -> color_eyre::Result<()> {
     if context.part == 1 {
         part1(parsed);
     } else {
-        part2(parsed);
+        part2(parsed)?;
     }
     let elapsed_part = humantime::format_duration(start.elapsed());
 
@@ -1,11 +1,44 @@
-use std::{collections::HashSet, str::FromStr, time::Instant};
-
-use ahash::HashMap;
-use aoc_2023::{load, print_res};
-use bstr::BString;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Instant,
+};
+
+use aoc_2023::{
+    interval::{Interval, IntervalBox},
+    load_with,
+    parse::{self, ParseError},
+    print_res,
+};
+use bstr::{BString, ByteSlice};
+use color_eyre::eyre::{ensure, eyre};
 use fxhash::FxHashSet;
 use itertools::Itertools;
 
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Write the bricks once they have fallen to this file
+    #[arg(long)]
+    export: Option<PathBuf>,
+    /// Format of the exported bricks
+    #[arg(long, value_enum, default_value = "puzzle")]
+    format: Format,
+    /// Ignored, day22 always runs on a single thread. Only accepted like in the parallel days
+    #[arg(long = "threads", value_name = "THREADS")]
+    _threads: Option<NonZeroUsize>,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Format {
+    /// Same format as the input, `x,y,z~x,y,z`
+    Puzzle,
+    /// Array of `{"a": [x, y, z], "b": [x, y, z]}` objects
+    Json,
+}
+
 #[derive(Clone, Copy, Debug)]
 struct Vec3 {
     x: u64,
@@ -38,8 +71,19 @@ pub struct Block {
 }
 
 impl Block {
-    fn height(&self) -> u64 {
-        self.a.z.abs_diff(self.b.z) + 1
+    fn span(a: u64, b: u64) -> Interval<u64> {
+        Interval::inclusive(a.min(b), a.max(b))
+    }
+
+    fn z(&self) -> Interval<u64> {
+        Self::span(self.a.z, self.b.z)
+    }
+
+    fn footprint(&self) -> IntervalBox<u64, 2> {
+        IntervalBox([
+            Self::span(self.a.x, self.b.x),
+            Self::span(self.a.y, self.b.y),
+        ])
     }
 }
 
@@ -51,142 +95,223 @@ impl FromStr for Block {
             .split_once('~')
             .ok_or_else(|| color_eyre::eyre::eyre!("Block must have two ends: {s}"))?;
 
-        Ok(Self {
+        let block = Self {
             a: a.parse()?,
             b: b.parse()?,
-        })
+        };
+
+        ensure!(block.a.z > 0, "Block is not above the ground: {s}");
+        ensure!(block.a.z <= block.b.z, "Block ends are inverted: {s}");
+
+        Ok(block)
+    }
+}
+
+impl std::fmt::Display for Block {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { a, b } = self;
+        write!(f, "{},{},{}~{},{},{}", a.x, a.y, a.z, b.x, b.y, b.z)
     }
 }
 
 type Parsed = Vec<Block>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    std::str::from_utf8(input)?
-        .lines()
-        .map(str::parse)
-        .collect()
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    input.lines().map(|l| parse::from_str(input, l)).collect()
+}
+
+/// Height reached by the bricks above each point of the ground, with the topmost brick
+struct HeightMap {
+    bounds: IntervalBox<u64, 2>,
+    cells: Vec<Option<(usize, u64)>>,
 }
 
-pub fn part1(mut input: Parsed) {
-    input.sort_by_key(|bl| std::cmp::min(bl.a.z, bl.b.z));
+impl HeightMap {
+    fn new(bricks: &[Block]) -> Self {
+        let bounds = bricks
+            .iter()
+            .map(Block::footprint)
+            .reduce(|a, b| {
+                IntervalBox(
+                    [0, 1]
+                        .map(|d| Interval::new(a[d].start.min(b[d].start), a[d].end.max(b[d].end))),
+                )
+            })
+            .unwrap_or(IntervalBox([Interval::empty(); 2]));
+
+        Self {
+            bounds,
+            cells: vec![None; bounds.volume::<u64>() as usize],
+        }
+    }
+
+    fn cell(&mut self, [x, y]: [u64; 2]) -> &mut Option<(usize, u64)> {
+        let [w, h] = self.bounds.0;
+        &mut self.cells[((x - w.start) * h.len() + (y - h.start)) as usize]
+    }
+}
 
-    let mut height_map = HashMap::default();
-    let mut multi_support = HashSet::new();
-    let mut single_support = HashSet::new();
-    let mut uncovered = HashSet::new();
+/// Bricks once they all fell, with how they rest on each other
+pub struct Settled {
+    /// Bricks at their final position, each one after the bricks it rests on
+    bricks: Vec<Block>,
+    supported_by: Vec<Vec<usize>>,
+    supports: Vec<Vec<usize>>,
+}
 
-    for (p, piece) in input.iter().enumerate() {
-        let x = std::cmp::min(piece.a.x, piece.b.x);
-        let y = std::cmp::min(piece.a.y, piece.b.y);
+impl Settled {
+    fn new(mut input: Parsed) -> color_eyre::Result<Self> {
+        input.sort_by_key(|bl| bl.z().start);
 
-        uncovered.insert(p);
+        let mut height_map = HeightMap::new(&input);
+        let mut supported_by = vec![Vec::new(); input.len()];
+        let mut supports = vec![Vec::new(); input.len()];
 
-        let span_x = piece.a.x.abs_diff(piece.b.x) + 1;
-        let span_y = piece.a.y.abs_diff(piece.b.y) + 1;
+        for p in 0..input.len() {
+            let piece = input[p];
+            let footprint = piece.footprint();
 
-        let mut height = 0;
-        for px in x..x + span_x {
-            for py in y..y + span_y {
-                if let Some(&(_, h)) = height_map.get(&(px, py)) {
+            let mut height = 0;
+            for point in footprint.points() {
+                if let Some((_, h)) = *height_map.cell(point) {
                     height = std::cmp::max(h, height);
                 }
             }
-        }
 
-        let after_height = height + piece.height();
-
-        let mut under = FxHashSet::default();
-        for px in x..x + span_x {
-            for py in y..y + span_y {
-                if let Some(&(u, h)) = height_map.get(&(px, py)) {
+            let mut under = FxHashSet::default();
+            for point in footprint.points() {
+                if let Some((u, h)) = *height_map.cell(point) {
                     if h == height {
                         under.insert(u);
-                        uncovered.remove(&u);
                     }
                 }
-
-                height_map.insert((px, py), (p, after_height));
             }
-        }
 
-        if under.len() > 1 {
-            multi_support.extend(under.into_iter());
-        } else {
-            single_support.extend(under.into_iter());
-        }
-    }
-
-    let multi_supporting = multi_support.difference(&single_support).count();
-
-    print_res!("Zappable count: {}", multi_supporting + uncovered.len());
-}
-
-fn heights(input: &Parsed, skip: Option<usize>) -> Vec<Option<u64>> {
-    let mut height_map = HashMap::default();
-    let mut pieces_height = vec![None; input.len()];
+            // Only a brick sharing cells with one below it can start under its resting height
+            let fall = piece.z().start.checked_sub(height + 1).ok_or_else(|| {
+                let below = under.iter().map(|&u| input[u]).join(", ");
+                eyre!("Block {piece} overlaps the blocks below it: {below}")
+            })?;
+            input[p].a.z -= fall;
+            input[p].b.z -= fall;
+            let piece = input[p];
+
+            for point in footprint.points() {
+                *height_map.cell(point) = Some((p, piece.z().end - 1));
+            }
 
-    for (p, piece) in input.iter().enumerate() {
-        if Some(p) == skip {
-            continue;
+            for &u in &under {
+                supports[u].push(p);
+            }
+            supported_by[p] = under.into_iter().sorted().collect();
         }
 
-        let x = std::cmp::min(piece.a.x, piece.b.x);
-        let y = std::cmp::min(piece.a.y, piece.b.y);
+        Ok(Self {
+            bricks: input,
+            supported_by,
+            supports,
+        })
+    }
 
-        let span_x = piece.a.x.abs_diff(piece.b.x) + 1;
-        let span_y = piece.a.y.abs_diff(piece.b.y) + 1;
+    fn export(&self, path: &Path, format: Format) -> color_eyre::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
 
-        let mut height = 0;
-        for px in x..x + span_x {
-            for py in y..y + span_y {
-                if let Some(&(_, h)) = height_map.get(&(px, py)) {
-                    height = std::cmp::max(h, height);
+        match format {
+            Format::Puzzle => {
+                for brick in &self.bricks {
+                    writeln!(out, "{brick}")?;
+                }
+            }
+            Format::Json => {
+                writeln!(out, "[")?;
+                for (i, Block { a, b }) in self.bricks.iter().enumerate() {
+                    let comma = if i + 1 == self.bricks.len() { "" } else { "," };
+                    writeln!(
+                        out,
+                        r#"  {{"a": [{}, {}, {}], "b": [{}, {}, {}]}}{comma}"#,
+                        a.x, a.y, a.z, b.x, b.y, b.z
+                    )?;
                 }
+                writeln!(out, "]")?;
             }
         }
 
-        pieces_height[p] = Some(height);
-        let after_height = height + piece.height();
+        out.flush()?;
+        Ok(())
+    }
 
-        let mut under = FxHashSet::default();
-        for px in x..x + span_x {
-            for py in y..y + span_y {
-                if let Some(&(u, h)) = height_map.get(&(px, py)) {
-                    if h == height {
-                        under.insert(u);
-                    }
+    /// Parent of each brick in the dominator tree rooted at the ground, with its depth
+    ///
+    /// A brick dominates another if removing it makes the other one fall.
+    fn dominators(&self) -> Vec<(Option<usize>, usize)> {
+        let mut dominators: Vec<(Option<usize>, usize)> = Vec::with_capacity(self.bricks.len());
+
+        // Lowest common ancestor in the tree, `None` being the ground
+        let common = |dominators: &[(Option<usize>, usize)], mut a: Option<usize>, mut b| {
+            let depth = |n: Option<usize>| n.map_or(0, |n| dominators[n].1);
+            while a != b {
+                if depth(a) >= depth(b) {
+                    a = a.and_then(|a| dominators[a].0);
+                } else {
+                    b = b.and_then(|b: usize| dominators[b].0);
                 }
-
-                height_map.insert((px, py), (p, after_height));
             }
+            a
+        };
+
+        for supporters in &self.supported_by {
+            let parent = match supporters.split_first() {
+                None => None,
+                Some((&first, rest)) => rest
+                    .iter()
+                    .try_fold(first, |a, &b| common(&dominators, Some(a), Some(b))),
+            };
+
+            let depth = parent.map_or(0, |p| dominators[p].1) + 1;
+            dominators.push((parent, depth));
         }
+
+        dominators
     }
+}
 
-    pieces_height
+fn settle(input: Parsed, options: &Options) -> color_eyre::Result<Settled> {
+    let settled = Settled::new(input)?;
+    if let Some(path) = &options.export {
+        settled.export(path, options.format)?;
+    }
+    Ok(settled)
 }
 
-pub fn part2(mut input: Parsed) {
-    input.sort_by_key(|bl| std::cmp::min(bl.a.z, bl.b.z));
+pub fn part1(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    let settled = settle(input, options)?;
+
+    let zappable = settled
+        .supports
+        .iter()
+        .filter(|above| above.iter().all(|&a| settled.supported_by[a].len() > 1))
+        .count();
 
-    let normal_heights = heights(&input, None);
+    print_res!("Zappable count: {zappable}");
+    Ok(())
+}
 
-    let sum_of_fall: usize = (0..input.len())
-        .map(|zapp| {
-            let zapped_heights = heights(&input, Some(zapp));
+pub fn part2(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    let settled = settle(input, options)?;
 
-            normal_heights
-                .iter()
-                .zip(zapped_heights)
-                .filter(|&(&normal, zapped)| zapped.is_some() && zapped != normal)
-                .count()
-        })
+    // Zapping a brick makes every brick it dominates fall
+    let sum_of_fall: usize = settled
+        .dominators()
+        .iter()
+        .map(|&(_, depth)| depth - 1)
         .sum();
 
     print_res!("Sum of falls: {sum_of_fall}");
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_with::<Options>()?;
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
@@ -194,9 +319,9 @@ pub fn main() -> color_eyre::Result<()> {
 
     let start = Instant::now();
     if context.part == 1 {
-        part1(parsed);
+        part1(parsed, &context.args)?;
     } else {
-        part2(parsed);
+        part2(parsed, &context.args)?;
     }
     let elapsed_part = humantime::format_duration(start.elapsed());
 
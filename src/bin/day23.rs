@@ -1,9 +1,19 @@
-use std::{collections::HashMap, time::Instant};
-
-use aoc_2023::{load, print_res};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use aoc_2023::{
+    load_with,
+    maze::{Junctions, Point},
+    parse::{self, ParseError},
+    print_res, Threads,
+};
 use bstr::{BString, ByteSlice};
+use color_eyre::eyre::{ensure, eyre};
 use itertools::Itertools;
-use petgraph::{algo::all_simple_paths, prelude::*};
+use petgraph::prelude::*;
+use rayon::prelude::*;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Cell {
@@ -17,217 +27,245 @@ pub enum Cell {
 
 type Parsed = Vec<Vec<Cell>>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    input
-        .lines()
-        .map(|l| {
-            let l = l.as_bstr();
-            l.iter()
-                .map(|&c| {
-                    Ok(match c {
-                        b'#' => Cell::Forest,
-                        b'.' => Cell::Path,
-                        b'>' => Cell::RightSlope,
-                        b'<' => Cell::LeftSlope,
-                        b'^' => Cell::UpSlope,
-                        b'v' => Cell::DownSlope,
-                        _ => color_eyre::eyre::bail!("Invalid cell: {}", c as char),
-                    })
-                })
-                .collect()
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    let grid: Parsed = parse::grid(input, |c| {
+        Some(match c {
+            b'#' => Cell::Forest,
+            b'.' => Cell::Path,
+            b'>' => Cell::RightSlope,
+            b'<' => Cell::LeftSlope,
+            b'^' => Cell::UpSlope,
+            b'v' => Cell::DownSlope,
+            _ => return None,
         })
-        .collect()
-}
+    })?;
+
+    // The first & last lines have a single opening, the start & the end of the hike
+    let lines = input.lines().collect_vec();
+    for (line, row) in [
+        (lines[0], &grid[0]),
+        (lines[lines.len() - 1], &grid[grid.len() - 1]),
+    ] {
+        if row.iter().filter(|&&c| c == Cell::Path).count() != 1 {
+            return Err(ParseError::at(
+                input,
+                line,
+                "Line must have exactly one path cell",
+            ));
+        }
+    }
 
-fn ends(input: &[Vec<Cell>]) -> (usize, usize) {
-    let ((start, _),) = input[0]
-        .iter()
-        .enumerate()
-        .filter(|&(_, &c)| c == Cell::Path)
-        .collect_tuple()
-        .unwrap();
-    let ((end, _),) = input
-        .last()
-        .unwrap()
-        .iter()
-        .enumerate()
-        .filter(|&(_, &c)| c == Cell::Path)
-        .collect_tuple()
-        .unwrap();
-
-    (start, end)
+    Ok(grid)
 }
 
-fn neighbours(x: usize, y: usize) -> [(usize, usize); 4] {
-    [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+fn ends(input: &[Vec<Cell>]) -> (usize, usize) {
+    let opening = |row: &[Cell]| row.iter().position(|&c| c == Cell::Path).unwrap();
+    (opening(&input[0]), opening(&input[input.len() - 1]))
 }
 
-fn longest_path(
-    to_x: usize,
-    to_y: usize,
-    visited: &mut im::HashSet<(usize, usize)>,
-    start: usize,
-    input: &[Vec<Cell>],
-    cache: &mut HashMap<(usize, usize), usize>,
-) -> usize {
-    if (to_x, to_y) == (start, 0) {
-        return 0;
+/// Whether `to` can be entered from `from`, slopes only being entered downhill
+fn downhill(from: Point, to: Point, cell: Cell) -> bool {
+    match cell {
+        Cell::Forest => false,
+        Cell::Path => true,
+        Cell::UpSlope => to.1 < from.1,
+        Cell::DownSlope => to.1 > from.1,
+        Cell::LeftSlope => to.0 < from.0,
+        Cell::RightSlope => to.0 > from.0,
     }
-
-    let mut longest = None;
-
-    for (nx, ny) in neighbours(to_x, to_y) {
-        let c = input[ny][nx];
-        if c != Cell::Forest
-            && !visited.contains(&(nx, ny))
-            && (c == Cell::Path
-                || (c == Cell::DownSlope && ny < to_y)
-                || (c == Cell::RightSlope && nx < to_x)
-                || (c == Cell::LeftSlope && nx > to_x)
-                || (c == Cell::UpSlope && ny > to_y))
-        {
-            let mut visited = visited.clone();
-            visited.insert((to_x, to_y));
-            let len = match cache.get(&(nx, ny)) {
-                Some(&l) => l,
-                None => {
-                    let v = longest_path(nx, ny, &mut visited, start, input, cache);
-                    cache.insert((to_x, to_y), v);
-                    v
-                }
-            };
-            longest = match longest {
-                Some(old) => Some(std::cmp::max(old, len)),
-                None => Some(len),
-            };
-        }
-    }
-
-    longest.unwrap() + 1
 }
 
-pub fn part1(input: Parsed) {
-    let (start, end) = ends(&input);
-    let mut cache = HashMap::new();
-
-    let mut set = im::HashSet::new();
-    set.insert((end, input.len() - 1));
-
-    let longest_len = longest_path(end, input.len() - 2, &mut set, start, &input, &mut cache) + 1;
+/// Longest simple path search between two junctions, of which there are at most 64
+struct LongestPath {
+    /// Junctions reachable from each junction, with the length of the corridor
+    edges: Vec<Vec<(usize, usize)>>,
+    /// Junctions reachable from each junction, as a bitmask
+    successors: Vec<u64>,
+    /// Length of the longest corridor reaching each junction
+    longest_in: Vec<usize>,
+    entry: usize,
+    exit: usize,
+    /// Only junction leading to the exit, from which the exit must be taken
+    last: Option<usize>,
+}
 
-    print_res!("Longest path: {longest_len}");
+#[derive(Clone, Copy, Debug)]
+struct Walk {
+    at: usize,
+    visited: u64,
+    len: usize,
 }
 
-fn intersections(input: &Parsed) -> Vec<(usize, usize)> {
-    let mut points = Vec::default();
-
-    for (y, line) in input.iter().enumerate().skip(1).take(input.len() - 2) {
-        for (x, &c) in line.iter().enumerate().skip(1).take(input[0].len() - 2) {
-            if c != Cell::Forest
-                && neighbours(x, y)
-                    .into_iter()
-                    .map(|(x, y)| input[y][x])
-                    .filter(|&c| c != Cell::Forest)
-                    .count()
-                    >= 3
-            {
-                points.push((x, y));
-            }
+impl LongestPath {
+    /// Junctions visited before searching the rest of the paths in parallel
+    const SPLIT_DEPTH: usize = 8;
+
+    fn new(
+        graph: &DiGraphMap<usize, usize>,
+        entry: usize,
+        exit: usize,
+    ) -> color_eyre::Result<Self> {
+        let count = graph.node_count();
+        ensure!(count <= 64, "Too many junctions to search: {count}");
+
+        let mut edges = vec![Vec::new(); count];
+        let mut successors = vec![0; count];
+        let mut longest_in = vec![0; count];
+        for (from, to, &len) in graph.all_edges().filter(|&(from, to, _)| from != to) {
+            edges[from].push((to, len));
+            successors[from] |= 1 << to;
+            longest_in[to] = std::cmp::max(longest_in[to], len);
         }
-    }
-
-    points
-}
 
-fn first_intersection(
-    mut current: (usize, usize),
-    mut from: (usize, usize),
-    input: &Parsed,
-    intersections: &[(usize, usize)],
-) -> ((usize, usize), usize) {
-    let mut len = 1;
-    loop {
-        if intersections.contains(&current) {
-            return (current, len);
-        } else {
-            let ((nx, ny),) = neighbours(current.0, current.1)
-                .into_iter()
-                .filter(|&(x, y)| (x, y) != from && input[y][x] != Cell::Forest)
-                .collect_tuple()
-                .unwrap();
-            from = current;
-            current = (nx, ny);
-            len += 1;
+        // Trying the longest corridors first finds long paths early, pruning more
+        for edges in &mut edges {
+            edges.sort_unstable_by_key(|&(_, len)| std::cmp::Reverse(len));
         }
+
+        let last = graph
+            .neighbors_directed(exit, Incoming)
+            .filter(|&last| last != exit)
+            .exactly_one()
+            .ok();
+
+        Ok(Self {
+            edges,
+            successors,
+            longest_in,
+            entry,
+            exit,
+            last,
+        })
     }
-}
 
-pub fn part2(input: Parsed) {
-    let (start, end) = ends(&input);
+    fn next(&self, walk: Walk) -> impl Iterator<Item = Walk> + '_ {
+        self.edges[walk.at]
+            .iter()
+            .filter(move |&&(to, _)| self.last != Some(walk.at) || to == self.exit)
+            .filter(move |&&(to, _)| walk.visited & 1 << to == 0)
+            .map(move |&(to, len)| Walk {
+                at: to,
+                visited: walk.visited | 1 << to,
+                len: walk.len + len,
+            })
+    }
 
-    let intersections = intersections(&input);
+    /// Longest corridors reaching the junctions still reachable, bounding what is left to walk.
+    /// `None` if the exit can't be reached anymore
+    fn bound(&self, walk: Walk) -> Option<usize> {
+        let mut reachable: u64 = 1 << walk.at;
+        let mut frontier = reachable;
+        while frontier != 0 {
+            let junction = frontier.trailing_zeros() as usize;
+            frontier &= frontier - 1;
+
+            let new = self.successors[junction] & !walk.visited & !reachable;
+            reachable |= new;
+            frontier |= new;
+        }
 
-    let (first, first_len) = first_intersection((start, 1), (start, 0), &input, &intersections);
-    let (last, last_len) = first_intersection(
-        (end, input.len() - 2),
-        (end, input.len() - 1),
-        &input,
-        &intersections,
-    );
+        if reachable & 1 << self.exit == 0 {
+            return None;
+        }
 
-    let neigh_idx = |neighbour| {
-        intersections
-            .iter()
-            .enumerate()
-            .find(|&(_, &c)| c == neighbour)
-            .unwrap()
-            .0
-    };
+        let mut bound = 0;
+        let mut rest = reachable & !(1 << walk.at);
+        while rest != 0 {
+            bound += self.longest_in[rest.trailing_zeros() as usize];
+            rest &= rest - 1;
+        }
 
-    let mut graph = UnGraphMap::new();
+        Some(bound)
+    }
 
-    for (i, &(ix, iy)) in intersections.iter().enumerate() {
-        if (ix, iy) == first || (ix, iy) == last {
-            continue;
+    fn search(&self, walk: Walk, best: &AtomicUsize) {
+        if walk.at == self.exit {
+            best.fetch_max(walk.len, Ordering::Relaxed);
+            return;
         }
 
-        for (nx, ny) in neighbours(ix, iy)
-            .into_iter()
-            .filter(|&(x, y)| (x, y) != (ix, iy) && input[y][x] != Cell::Forest)
-        {
-            let (neighbour, dist) = first_intersection((nx, ny), (ix, iy), &input, &intersections);
-            let neighbour = neigh_idx(neighbour);
-
-            graph.add_edge(i, neighbour, dist);
+        for next in self.next(walk) {
+            match self.bound(next) {
+                Some(bound) if next.len + bound > best.load(Ordering::Relaxed) => {
+                    self.search(next, best)
+                }
+                _ => (),
+            }
         }
     }
 
-    let mut largest_len = 0;
+    fn longest(&self) -> Option<usize> {
+        let start = Walk {
+            at: self.entry,
+            visited: 1 << self.entry,
+            len: 0,
+        };
+
+        let mut walks = vec![start];
+        for _ in 0..Self::SPLIT_DEPTH {
+            let mut deeper = Vec::new();
+            for walk in walks {
+                match walk.at == self.exit {
+                    true => deeper.push(walk),
+                    false => deeper.extend(self.next(walk)),
+                }
+            }
+            walks = deeper;
+        }
+
+        let best = AtomicUsize::new(0);
+        walks
+            .into_par_iter()
+            .for_each(|walk| self.search(walk, &best));
 
-    for path in all_simple_paths::<Vec<_>, _>(&graph, neigh_idx(first), neigh_idx(last), 0, None) {
-        let len: usize = path
-            .windows(2)
-            .map(|w| *graph.edge_weight(w[0], w[1]).unwrap())
-            .sum();
-        largest_len = std::cmp::max(largest_len, len + first_len + last_len);
+        Some(best.into_inner()).filter(|&l| l != 0)
     }
+}
 
+fn longest_hike(input: &Parsed, slopes: bool) -> color_eyre::Result<usize> {
+    let (start, end) = ends(input);
+    let (start, end) = ((start, 0), (end, input.len() - 1));
+
+    // The ends come first in the junctions
+    let junctions = Junctions::new(input[0].len(), input.len(), &[start, end], |from, to| {
+        let (from_cell, to_cell) = (input[from.1][from.0], input[to.1][to.0]);
+        match slopes {
+            true => from_cell != Cell::Forest && downhill(from, to, to_cell),
+            false => from_cell != Cell::Forest && to_cell != Cell::Forest,
+        }
+    });
+    LongestPath::new(&junctions.graph, 0, 1)?
+        .longest()
+        .ok_or_else(|| eyre!("No path reaches the exit"))
+}
+
+pub fn part1(input: Parsed) -> color_eyre::Result<()> {
+    let longest_len = longest_hike(&input, true)?;
+    print_res!("Longest path: {longest_len}");
+    Ok(())
+}
+
+pub fn part2(input: Parsed) -> color_eyre::Result<()> {
+    let largest_len = longest_hike(&input, false)?;
     print_res!("Largest path possible: {largest_len}");
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_with::<Threads>()?;
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
     let elapsed = humantime::format_duration(start.elapsed());
 
     let start = Instant::now();
-    if context.part == 1 {
-        part1(parsed);
-    } else {
-        part2(parsed);
-    }
+    context.args.install(|| {
+        if context.part == 1 {
+            part1(parsed)
+        } else {
+            part2(parsed)
+        }
+    })??;
     let elapsed_part = humantime::format_duration(start.elapsed());
 
     println!("  Parsing: {elapsed}");
@@ -1,12 +1,29 @@
-use std::time::Instant;
+use std::{cmp::Ordering, ops::RangeInclusive, time::Instant};
 
-use aoc_2023::{load, print_res};
-use bstr::{BString, ByteSlice};
-use itertools::Itertools;
-use z3::{
-    ast::{Ast, Int},
-    SatResult,
+use aoc_2023::{
+    load_with,
+    parse::{self, ParseError},
+    print_res, Threads,
 };
+use bstr::BString;
+use color_eyre::eyre::{bail, eyre};
+use itertools::Itertools;
+use rayon::prelude::*;
+
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Lowest x and y coordinate of the test area
+    #[arg(long, default_value_t = 200000000000000)]
+    area_min: i64,
+    /// Highest x and y coordinate of the test area
+    #[arg(long, default_value_t = 400000000000000)]
+    area_max: i64,
+    /// Find the rock with the z3 solver instead of the native one, needs the `z3` feature
+    #[arg(long)]
+    z3: bool,
+    #[command(flatten)]
+    threads: Threads,
+}
 
 #[derive(Debug)]
 struct Vec3 {
@@ -23,24 +40,27 @@ pub struct Hailstone {
 
 type Parsed = Vec<Hailstone>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    std::str::from_utf8(input)?
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    let error = |span: &str, message| ParseError::at(input, span.as_bytes(), message);
+    let number = |n: &str| parse::from_str(input, n.trim().as_bytes());
+
+    parse::utf8(input)?
         .lines()
         .map(|l| {
             let (pos, vel) = l
                 .split_once(" @ ")
-                .ok_or_else(|| color_eyre::eyre::eyre!("Missing line separator in: {l}"))?;
+                .ok_or_else(|| error(l, "Missing ' @ ' between the position and velocity"))?;
 
             let vec3 = |s: &str| {
-                let Some((x, y, z)) = s.split(", ").map(str::trim).map(str::parse).collect_tuple()
-                else {
-                    color_eyre::eyre::bail!("Invalid vec3: {s}")
-                };
+                let (x, y, z) = s
+                    .split(", ")
+                    .collect_tuple()
+                    .ok_or_else(|| error(s, "Vector must have exactly three coordinates"))?;
 
                 Ok(Vec3 {
-                    x: x?,
-                    y: y?,
-                    z: z?,
+                    x: number(x)?,
+                    y: number(y)?,
+                    z: number(z)?,
                 })
             };
 
@@ -52,70 +72,288 @@ pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
         .collect()
 }
 
-impl Hailstone {
-    fn collision_2d(&self, other: &Self) -> Option<(f64, f64)> {
-        let x1 = self.pos.x;
-        let y1 = self.pos.y;
+/// Exact fraction, with a positive denominator
+#[derive(Clone, Copy, Debug)]
+struct Ratio {
+    num: i128,
+    den: i128,
+}
+
+impl Ratio {
+    fn new(num: impl Into<i128>, den: impl Into<i128>) -> Self {
+        let (num, den) = (num.into(), den.into());
+        assert_ne!(den, 0, "Zero denominator");
+        match den < 0 {
+            true => Self {
+                num: -num,
+                den: -den,
+            },
+            false => Self { num, den },
+        }
+    }
+}
 
-        let x2 = self.pos.x + self.vel.x;
-        let y2 = self.pos.y + self.vel.y;
+impl From<i64> for Ratio {
+    fn from(value: i64) -> Self {
+        Self::new(value, 1)
+    }
+}
+
+impl PartialEq for Ratio {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
 
-        let x3 = other.pos.x;
-        let y3 = other.pos.y;
+impl Eq for Ratio {}
 
-        let x4 = other.pos.x + other.vel.x;
-        let y4 = other.pos.y + other.vel.y;
+impl PartialOrd for Ratio {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-        let denom = (x1 - x2) * (y3 - y4) - (y1 - y2) * (x3 - x4);
+impl Ord for Ratio {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.num * other.den).cmp(&(other.num * self.den))
+    }
+}
 
-        if denom == 0 {
-            return None;
+/// How the paths of two hailstones meet in the x,y plane
+#[derive(Debug)]
+enum Crossing {
+    /// The paths never meet
+    Parallel,
+    /// The paths are on the same line
+    Coincident,
+    /// The lines meet at `(x, y)`, reached at `t` by the first hailstone and `s` by the second
+    Point {
+        x: Ratio,
+        y: Ratio,
+        t: Ratio,
+        s: Ratio,
+    },
+}
+
+impl Hailstone {
+    fn crossing_2d(&self, other: &Self) -> Crossing {
+        let (p1, v1) = (&self.pos, &self.vel);
+        let (p2, v2) = (&other.pos, &other.vel);
+        let (v1x, v1y) = (v1.x as i128, v1.y as i128);
+        let (v2x, v2y) = (v2.x as i128, v2.y as i128);
+        let (dx, dy) = (p2.x as i128 - p1.x as i128, p2.y as i128 - p1.y as i128);
+
+        // p1 + t * v1 = p2 + s * v2, solved with Cramer's rule
+        let det = v2x * v1y - v1x * v2y;
+        let t = v2x * dy - v2y * dx;
+        let s = v1x * dy - v1y * dx;
+
+        match det {
+            0 if s == 0 => Crossing::Coincident,
+            0 => Crossing::Parallel,
+            _ => Crossing::Point {
+                x: Ratio::new(p1.x as i128 * det + t * v1x, det),
+                y: Ratio::new(p1.y as i128 * det + t * v1y, det),
+                t: Ratio::new(t, det),
+                s: Ratio::new(s, det),
+            },
+        }
+    }
+
+    /// Whether the paths of two hailstones on the same line share a point in the area
+    fn overlap_2d(&self, other: &Self, area: &RangeInclusive<i64>) -> bool {
+        let (p, v) = (&self.pos, &self.vel);
+
+        // Points of the line are p + u * v, self going through u >= 0
+        let mut lowers = vec![Ratio::from(0)];
+        let mut uppers = Vec::new();
+
+        let start = match v.x {
+            0 => Ratio::new(other.pos.y as i128 - p.y as i128, v.y),
+            _ => Ratio::new(other.pos.x as i128 - p.x as i128, v.x),
+        };
+        let dot = v.x as i128 * other.vel.x as i128 + v.y as i128 * other.vel.y as i128;
+        match dot > 0 {
+            true => lowers.push(start),
+            false => uppers.push(start),
         }
 
-        let (x1, x2, x3, x4) = (x1 as i128, x2 as i128, x3 as i128, x4 as i128);
-        let (y1, y2, y3, y4) = (y1 as i128, y2 as i128, y3 as i128, y4 as i128);
+        for (p, v) in [(p.x, v.x), (p.y, v.y)] {
+            if v == 0 {
+                if !area.contains(&p) {
+                    return false;
+                }
+                continue;
+            }
 
-        let p_nom_x = (x1 * y2 - y1 * x2) * (x3 - x4) - (x1 - x2) * (x3 * y4 - y3 * x4);
-        let p_nom_y = (x1 * y2 - y1 * x2) * (y3 - y4) - (y1 - y2) * (x3 * y4 - y3 * x4);
+            let (a, b) = (
+                Ratio::new(*area.start() as i128 - p as i128, v),
+                Ratio::new(*area.end() as i128 - p as i128, v),
+            );
+            lowers.push(std::cmp::min(a, b));
+            uppers.push(std::cmp::max(a, b));
+        }
 
-        let denom = denom as f64;
-        Some((p_nom_x as f64 / denom, p_nom_y as f64 / denom))
+        lowers.into_iter().max() <= uppers.into_iter().min()
     }
 
-    // x(t) = x_0 + v_x_0 * t
-    // x(t) - x_0 = v_x_0 * t
-    // (x(t) - x_0) / v_x_0 = t
-    fn time(&self, x: f64) -> f64 {
-        (x - self.pos.x as f64) / self.vel.x as f64
+    fn collides_2d(&self, other: &Self, area: &RangeInclusive<i64>) -> bool {
+        let zero = Ratio::from(0);
+        let (start, end) = (Ratio::from(*area.start()), Ratio::from(*area.end()));
+        let inside = |c| start <= c && c <= end;
+
+        match self.crossing_2d(other) {
+            Crossing::Parallel => false,
+            Crossing::Coincident => self.overlap_2d(other, area),
+            Crossing::Point { x, y, t, s } => t >= zero && s >= zero && inside(x) && inside(y),
+        }
     }
 }
 
-pub fn part1(input: Parsed) {
-    let min = 200000000000000.;
-    let max = 400000000000000.;
+pub fn part1(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    if let Some(still) = input.iter().find(|h| h.vel.x == 0 && h.vel.y == 0) {
+        bail!("Hailstone does not move in the x,y plane: {still:?}")
+    }
 
-    let range = min..=max;
+    let area = options.area_min..=options.area_max;
 
-    let mut collision_count = 0;
+    let collision_count: usize = input
+        .par_iter()
+        .enumerate()
+        .map(|(i, a)| {
+            input[i + 1..]
+                .iter()
+                .filter(|b| a.collides_2d(b, &area))
+                .count()
+        })
+        .sum();
 
-    for (i, a) in input.iter().enumerate() {
-        for b in &input[i + 1..] {
-            if let Some((c_x, c_y)) = a.collision_2d(b) {
-                if a.time(c_x) >= 0.
-                    && b.time(c_x) >= 0.
-                    && range.contains(&c_x)
-                    && range.contains(&c_y)
-                {
-                    collision_count += 1;
-                }
-            }
+    print_res!("Number of x,y collisions: {collision_count}");
+
+    Ok(())
+}
+
+type Exact = [i128; 3];
+
+fn exact(v: &Vec3) -> Exact {
+    [v.x as i128, v.y as i128, v.z as i128]
+}
+
+fn sub(a: Exact, b: Exact) -> Option<Exact> {
+    Some([
+        a[0].checked_sub(b[0])?,
+        a[1].checked_sub(b[1])?,
+        a[2].checked_sub(b[2])?,
+    ])
+}
+
+/// `a + t * b`
+fn mul_add(a: Exact, t: i128, b: Exact) -> Option<Exact> {
+    let term = |k: usize| t.checked_mul(b[k])?.checked_add(a[k]);
+    Some([term(0)?, term(1)?, term(2)?])
+}
+
+fn dot(a: Exact, b: Exact) -> Option<i128> {
+    a[0].checked_mul(b[0])?
+        .checked_add(a[1].checked_mul(b[1])?)?
+        .checked_add(a[2].checked_mul(b[2])?)
+}
+
+fn cross(a: Exact, b: Exact) -> Option<Exact> {
+    let det = |i: usize, j: usize| a[i].checked_mul(b[j])?.checked_sub(a[j].checked_mul(b[i])?);
+    Some([det(1, 2)?, det(2, 0)?, det(0, 1)?])
+}
+
+/// Rock thrown from `pos` at `vel`
+#[derive(Debug)]
+struct Rock {
+    pos: Exact,
+    vel: Exact,
+}
+
+impl Rock {
+    /// Rock hitting the three hailstones, `None` if it is not unique or not at integer coordinates.
+    ///
+    /// Seen from `a` the rock goes through the origin, so it stays in the plane holding the origin
+    /// and the path of `b`: it hits `c` when `c` crosses that plane, and `b` when `b` crosses the
+    /// plane of `c`.
+    fn through(a: &Hailstone, b: &Hailstone, c: &Hailstone) -> Option<Self> {
+        let relative = |h: &Hailstone| {
+            Some((
+                sub(exact(&h.pos), exact(&a.pos))?,
+                sub(exact(&h.vel), exact(&a.vel))?,
+            ))
+        };
+        let (b_pos, b_vel) = relative(b)?;
+        let (c_pos, c_vel) = relative(c)?;
+
+        // Solves (pos + t * vel) . normal = 0 for the normal of the other plane
+        let crossing = |pos, vel, plane: Exact| {
+            let (num, den) = (dot(pos, plane)?, dot(vel, plane)?);
+            (den != 0 && num % den == 0).then(|| -num / den)
+        };
+        let b_time = crossing(b_pos, b_vel, cross(c_pos, c_vel)?)?;
+        let c_time = crossing(c_pos, c_vel, cross(b_pos, b_vel)?)?;
+        if b_time == c_time {
+            return None;
         }
+
+        let b_hit = mul_add(exact(&b.pos), b_time, exact(&b.vel))?;
+        let c_hit = mul_add(exact(&c.pos), c_time, exact(&c.vel))?;
+        let dt = c_time - b_time;
+
+        let travel = sub(c_hit, b_hit)?;
+        if travel.iter().any(|d| d % dt != 0) {
+            return None;
+        }
+        let vel = travel.map(|d| d / dt);
+        let pos = mul_add(b_hit, -b_time, vel)?;
+
+        Some(Self { pos, vel })
     }
 
-    print_res!("Number of x,y collisions: {collision_count}");
+    /// Whether the rock hits the hailstone at a non negative time
+    fn hits(&self, hailstone: &Hailstone) -> bool {
+        // pos + t * vel = h_pos + t * h_vel, so pos - h_pos = t * (h_vel - vel)
+        let (Some(offset), Some(closing)) = (
+            sub(self.pos, exact(&hailstone.pos)),
+            sub(exact(&hailstone.vel), self.vel),
+        ) else {
+            return false;
+        };
+
+        if cross(offset, closing) != Some([0; 3]) {
+            return false;
+        }
+
+        // Parallel and in the same direction, or at the same place
+        (0..3).all(|k| offset[k] == 0 || offset[k].signum() == closing[k].signum())
+    }
 }
 
-pub fn part2(input: Parsed) {
+/// Finds the rock from the first three hailstones that give one, checking it against all of them
+fn native_rock(input: &Parsed) -> color_eyre::Result<Exact> {
+    let rock = input
+        .iter()
+        .tuple_combinations()
+        .find_map(|(a, b, c)| Rock::through(a, b, c))
+        .ok_or_else(|| eyre!("No rock hits three of the hailstones"))?;
+
+    if let Some((i, missed)) = input.iter().enumerate().find(|(_, h)| !rock.hits(h)) {
+        bail!("Rock {rock:?} misses hailstone {i}: {missed:?}")
+    }
+
+    Ok(rock.pos)
+}
+
+#[cfg(feature = "z3")]
+fn z3_rock(input: &Parsed) -> color_eyre::Result<Exact> {
+    use color_eyre::eyre::ensure;
+    use z3::{
+        ast::{Ast, Int},
+        SatResult,
+    };
+
     let z3_cfg = z3::Config::new();
     let z3 = z3::Context::new(&z3_cfg);
     let solver = z3::Solver::new(&z3);
@@ -152,29 +390,59 @@ pub fn part2(input: Parsed) {
         solver.assert(&rz._eq(&hz));
     }
 
-    assert_eq!(solver.check(), SatResult::Sat);
-    let model = solver.get_model().unwrap();
+    ensure!(
+        solver.check() == SatResult::Sat,
+        "No rock hits the hailstones"
+    );
+    let model = solver
+        .get_model()
+        .ok_or_else(|| eyre!("Missing z3 model"))?;
+
+    let coord = |c: &Int| {
+        model
+            .get_const_interp(c)
+            .and_then(|c| c.as_i64())
+            .ok_or_else(|| eyre!("Rock coordinate is not an integer"))
+    };
+
+    Ok([
+        coord(&x0)? as i128,
+        coord(&y0)? as i128,
+        coord(&z0)? as i128,
+    ])
+}
+
+#[cfg(not(feature = "z3"))]
+fn z3_rock(_: &Parsed) -> color_eyre::Result<Exact> {
+    bail!("Built without the z3 feature")
+}
 
-    let x0 = model.get_const_interp(&x0).unwrap().as_i64().unwrap();
-    let y0 = model.get_const_interp(&y0).unwrap().as_i64().unwrap();
-    let z0 = model.get_const_interp(&z0).unwrap().as_i64().unwrap();
+pub fn part2(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    let [x0, y0, z0] = match options.z3 {
+        true => z3_rock(&input)?,
+        false => native_rock(&input)?,
+    };
 
-    print_res!("Sum of coords: {}", x0 + y0 + z0)
+    print_res!("Sum of coords: {}", x0 + y0 + z0);
+
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_with::<Options>()?;
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
     let elapsed = humantime::format_duration(start.elapsed());
 
     let start = Instant::now();
-    if context.part == 1 {
-        part1(parsed);
-    } else {
-        part2(parsed);
-    }
+    context.args.threads.install(|| {
+        if context.part == 1 {
+            part1(parsed, &context.args)
+        } else {
+            part2(parsed, &context.args)
+        }
+    })??;
     let elapsed_part = humantime::format_duration(start.elapsed());
 
     println!("  Parsing: {elapsed}");
@@ -1,6 +1,6 @@
 use std::time::Instant;
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{load, parse::ParseError, print_res};
 use bstr::{BString, ByteSlice};
 use itertools::Itertools;
 use petgraph::{graphmap::UnGraphMap, visit::Bfs};
@@ -42,19 +42,20 @@ impl Component {
 
 type Parsed = Vec<(Component, Vec<Component>)>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    let component =
+        |name: &[u8]| Component::from_bytes(name).map_err(|e| ParseError::at(input, name, e));
+
     input
         .lines()
         .map(|l| {
             let (from, to) = l
                 .split_once_str(": ")
-                .ok_or_else(|| color_eyre::eyre::eyre!("Malformed line: {}", l.as_bstr()))?;
+                .ok_or_else(|| ParseError::at(input, l, "Missing ': ' after the component"))?;
 
             Ok((
-                Component::from_bytes(from)?,
-                to.split(|&c| c == b' ')
-                    .map(Component::from_bytes)
-                    .try_collect()?,
+                component(from)?,
+                to.split(|&c| c == b' ').map(component).try_collect()?,
             ))
         })
         .collect()
@@ -98,7 +99,7 @@ pub fn part1(input: Parsed) {
     print_res!("Product of component sizes: {}", size_a * size_b);
 }
 
-pub fn part2(input: Parsed) {
+pub fn part2(_input: Parsed) {
     todo!("todo part2")
 }
 
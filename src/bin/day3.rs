@@ -3,14 +3,43 @@ use std::{
     time::Instant,
 };
 
-use aoc_2023::{load, print_res, parse_u64_bytes};
+use aoc_2023::{load, parse::ParseError, parse_u64_bytes, print_res};
 use bstr::{BStr, BString, ByteSlice};
 use regex::bytes::Regex;
 
 type Parsed<'a> = Vec<&'a BStr>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    Ok(input.lines().map(|l| l.as_bstr()).collect())
+pub fn parsing(input: &BString) -> Result<Parsed<'_>, ParseError> {
+    let lines: Parsed = input.lines().map(|l| l.as_bstr()).collect();
+
+    let width = lines.first().map_or(0, |l| l.len());
+    let regex = number_regex();
+    for &line in &lines {
+        if line.len() != width {
+            return Err(ParseError::at(
+                input,
+                line,
+                format!("Line is not {width} characters long"),
+            ));
+        }
+
+        if let Some(m) = regex
+            .find_iter(line)
+            .find(|m| parse_u64_bytes(m.as_bytes()).is_none())
+        {
+            return Err(ParseError::at(input, m.as_bytes(), "Number is too large"));
+        }
+    }
+
+    Ok(lines)
+}
+
+fn number_regex() -> Regex {
+    Regex::new(r#"\d+"#).unwrap()
+}
+
+fn parse_number(number: &[u8]) -> u64 {
+    parse_u64_bytes(number).expect("Numbers are checked while parsing")
 }
 
 fn neighbours<'a>(
@@ -34,7 +63,7 @@ fn neighbours<'a>(
 }
 
 pub fn part1(input: Parsed) {
-    let regex = Regex::new(r#"\d+"#).unwrap();
+    let regex = number_regex();
     let input = &input;
 
     let part_number_sum: u64 = input
@@ -48,14 +77,14 @@ pub fn part1(input: Parsed) {
                         .flat_map(|x| neighbours(input, x, y))
                         .any(|(_, _, c)| !c.is_ascii_digit() && c != b'.')
                 })
-                .map(|m| parse_u64_bytes(m.as_bytes()))
+                .map(|m| parse_number(m.as_bytes()))
         })
         .sum();
     print_res!("Sum of part numbers: {part_number_sum}");
 }
 
 pub fn part2(input: Parsed) {
-    let regex = Regex::new(r#"\d+"#).unwrap();
+    let regex = number_regex();
     let input = &input;
 
     let stars = input
@@ -63,7 +92,7 @@ pub fn part2(input: Parsed) {
         .enumerate()
         .flat_map(|(y, line)| {
             regex.find_iter(line).flat_map(move |m| {
-                let num = parse_u64_bytes(m.as_bytes());
+                let num = parse_number(m.as_bytes());
                 let loc = (m.start(), y);
                 m.range()
                     .flat_map(move |x| neighbours(input, x, y))
@@ -1,8 +1,12 @@
-use std::{collections::HashSet, time::Instant};
-
-use aoc_2023::{load, parse_u64_bytes, print_res};
+use std::{
+    collections::{HashSet, VecDeque},
+    time::Instant,
+};
+
+use aoc_2023::{
+    load_streamable, parse::ParseError, parse_u64_bytes, print_res, stream::Records, NoArgs,
+};
 use bstr::{BString, ByteSlice};
-use color_eyre::eyre::{ensure, eyre};
 
 #[derive(Debug)]
 pub struct Card {
@@ -12,41 +16,60 @@ pub struct Card {
 
 type Parsed = Vec<Card>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
+fn parse_card(i: usize, line: &[u8]) -> Result<Card, ParseError> {
+    let error = |span: &[u8], message: String| ParseError::at(line, span, message);
+    let number = |n: &[u8]| parse_u64_bytes(n).ok_or_else(|| error(n, "Invalid number".into()));
+
+    let parse = || {
+        let (prefix, numbers) = line
+            .split_once_str(":")
+            .ok_or_else(|| error(line, "Missing ':' after the card number".into()))?;
+
+        let id = prefix
+            .strip_prefix(b"Card")
+            .ok_or_else(|| error(prefix, "Card not starting with 'Card'".into()))?
+            .trim();
+        if number(id)? != i as u64 + 1 {
+            return Err(error(
+                id,
+                format!("Card is misnumbered, expected {}", i + 1),
+            ));
+        }
+
+        let parse_set = |set: &[u8]| -> Result<HashSet<_>, _> {
+            set.split(|&c| c == b' ')
+                .filter(|c| !c.is_empty())
+                .map(number)
+                .collect()
+        };
+        let (winning, drawn) = numbers
+            .split_once_str("|")
+            .ok_or_else(|| error(numbers, "Missing '|' between the numbers".into()))?;
+
+        Ok(Card {
+            winning: parse_set(winning)?,
+            drawn: parse_set(drawn)?,
+        })
+    };
+
+    parse().map_err(|e| e.on_line(i + 1))
+}
+
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
     input
         .lines()
         .enumerate()
-        .map(|(i, line)| {
-            let line = line.as_bstr();
-            let malformed = || eyre!("Malformed card: {line}");
-            let (prefix, numbers) = line.split_once_str(":").ok_or_else(malformed)?;
-            ensure!(
-                parse_u64_bytes(prefix.strip_prefix(b"Card").ok_or_else(malformed)?.trim())
-                    as usize
-                    == i + 1,
-                "Bad card index"
-            );
-
-            let parse_set = |set: &[u8]| {
-                set.trim()
-                    .split(|&c| c == b' ')
-                    .filter(|c| !c.is_empty())
-                    .map(parse_u64_bytes)
-                    .collect()
-            };
-            let (winning, drawn) = numbers.split_once_str("|").ok_or_else(malformed)?;
-
-            Ok(Card {
-                winning: parse_set(winning),
-                drawn: parse_set(drawn),
-            })
-        })
+        .map(|(i, line)| parse_card(i, line))
         .collect()
 }
 
 impl Card {
+    fn wins(&self) -> usize {
+        self.winning.intersection(&self.drawn).count()
+    }
+
     fn points(&self) -> usize {
-        let winning_drawn = self.winning.intersection(&self.drawn).count();
+        let winning_drawn = self.wins();
 
         if winning_drawn == 0 {
             0
@@ -64,7 +87,7 @@ pub fn part1(input: Parsed) {
 pub fn part2(input: Parsed) {
     let mut cards = vec![1; input.len()];
     for (idx, card) in input.iter().enumerate() {
-        let win_count = card.winning.intersection(&card.drawn).count();
+        let win_count = card.wins();
         let copies = cards[idx];
 
         if win_count > 0 {
@@ -79,8 +102,52 @@ pub fn part2(input: Parsed) {
     print_res!("Total cards at the end: {total_cards}");
 }
 
+pub fn stream_part1(records: Records) -> color_eyre::Result<()> {
+    let total_points: usize = records.par_sum(|i, line| Ok(parse_card(i, line)?.points()))?;
+    print_res!("Total points are: {total_points}");
+    Ok(())
+}
+
+pub fn stream_part2(records: Records) -> color_eyre::Result<()> {
+    // Copies won by the previous cards for the next ones
+    let mut won = VecDeque::new();
+    let mut total_cards = 0;
+
+    records.try_for_each(|i, line| {
+        let win_count = parse_card(i, line)?.wins();
+        let copies = 1 + won.pop_front().unwrap_or(0);
+        total_cards += copies;
+
+        if won.len() < win_count {
+            won.resize(win_count, 0);
+        }
+        for other in won.iter_mut().take(win_count) {
+            *other += copies;
+        }
+
+        Ok(())
+    })?;
+
+    print_res!("Total cards at the end: {total_cards}");
+    Ok(())
+}
+
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_streamable::<NoArgs>()?;
+
+    if context.args.stream {
+        let start = Instant::now();
+        let records = context.records(b'\n')?;
+        if context.part == 1 {
+            stream_part1(records)?;
+        } else {
+            stream_part2(records)?;
+        }
+        let elapsed = humantime::format_duration(start.elapsed());
+
+        println!("  Streaming: {elapsed}");
+        return Ok(());
+    }
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
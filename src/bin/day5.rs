@@ -1,232 +1,359 @@
-use std::{collections::BTreeMap, time::Instant};
-
-use aoc_2023::{load, print_res};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound::Excluded,
+    time::Instant,
+};
+
+use aoc_2023::{
+    interval::{Interval, IntervalSet},
+    load_with,
+    parse::{self, ParseError},
+    print_res,
+};
 use bstr::BString;
 use color_eyre::eyre::{self, eyre};
 
-#[derive(Debug, Clone, Copy)]
-pub struct Interval {
-    pub start: u64,
-    pub len: u64,
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Category of the values listed in the `seeds:` section
+    #[arg(long, default_value = "seed")]
+    from: String,
+    /// Category to map the values to
+    #[arg(long, default_value = "location")]
+    to: String,
+    /// Instead of solving, find which seeds give this value
+    #[arg(long)]
+    reverse: Option<u64>,
 }
 
-impl std::cmp::PartialEq for Interval {
-    fn eq(&self, other: &Self) -> bool {
-        self.start == other.start
+/// Piecewise linear map from source intervals to destination starts, identity elsewhere
+#[derive(Debug, Clone, Default)]
+pub struct Mapping(BTreeMap<Interval<u64>, u64>);
+
+impl Mapping {
+    fn translate(&self, element: u64) -> u64 {
+        match self
+            .0
+            .range(..=Interval::new(element, u64::MAX))
+            .next_back()
+        {
+            Some((i, to)) if i.contains(element) => to + (element - i.start),
+            _ => element,
+        }
     }
-}
 
-impl std::cmp::Eq for Interval {}
+    /// Splits the range along the pieces of the mapping, with the destination of each
+    fn segments(&self, range: Interval<u64>) -> Vec<(Interval<u64>, u64)> {
+        if range.is_empty() {
+            return Vec::new();
+        }
 
-impl std::cmp::PartialOrd for Interval {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
+        let first = self
+            .0
+            .range(..=Interval::new(range.start, u64::MAX))
+            .next_back();
+        let others = self.0.range((
+            Excluded(Interval::new(range.start, u64::MAX)),
+            Excluded(Interval::new(range.end, 0)),
+        ));
 
-impl std::cmp::Ord for Interval {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.start.cmp(&other.start)
-    }
-}
+        let mut segments = Vec::new();
+        let mut rest = range;
 
-type Mapping = BTreeMap<Interval, u64>;
+        for (&from, &to) in first.into_iter().chain(others) {
+            let (before, after) = rest.split_at(from.start);
+            let (inside, after) = after.split_at(from.end);
 
-#[derive(Debug)]
-pub struct Mappings {
-    pub seed_to_soil: Mapping,
-    pub soil_to_fertilizer: Mapping,
-    pub fertilizer_to_water: Mapping,
-    pub water_to_light: Mapping,
-    pub light_to_temperature: Mapping,
-    pub temperature_to_humidity: Mapping,
-    pub humidity_to_location: Mapping,
-}
+            if !before.is_empty() {
+                segments.push((before, before.start));
+            }
+            if !inside.is_empty() {
+                segments.push((inside, to + (inside.start - from.start)));
+            }
+            rest = after;
+        }
 
-type Parsed = (Vec<u64>, Mappings);
+        if !rest.is_empty() {
+            segments.push((rest, rest.start));
+        }
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    let input = std::str::from_utf8(input)?;
+        segments
+    }
 
-    let mut sections = input.split("\n\n");
-    let seeds = sections
-        .next()
-        .ok_or_else(|| eyre!("Missing seed section"))?
-        .strip_prefix("seeds:")
-        .ok_or_else(|| eyre!("Invalid section name"))?
-        .split_whitespace()
-        .map(str::parse)
-        .collect::<Result<Vec<u64>, _>>()?;
+    /// Every value that is translated to `element`
+    fn preimages(&self, element: u64) -> Vec<u64> {
+        let mut preimages = self
+            .0
+            .iter()
+            .filter(|&(from, &to)| (to..to + from.len()).contains(&element))
+            .map(|(from, &to)| from.start + (element - to))
+            .collect::<Vec<_>>();
+
+        let mapped = self
+            .0
+            .range(..=Interval::new(element, u64::MAX))
+            .next_back()
+            .is_some_and(|(i, _)| i.contains(element));
+        if !mapped {
+            preimages.push(element);
+        }
 
-    fn parse_mapping<'a>(
-        mut mapping: impl Iterator<Item = &'a str>,
-        name: &str,
-    ) -> color_eyre::Result<Mapping> {
-        let section = mapping
-            .next()
-            .ok_or_else(|| eyre!("Missing section {name}"))?;
+        preimages.sort_unstable();
+        preimages.dedup();
+        preimages
+    }
 
-        let values = section
-            .strip_prefix(&format!("{name} map:\n"))
-            .ok_or_else(|| eyre!("Invalid section name '{name}': {section}"))?;
+    fn translate_range(&self, range: Interval<u64>) -> impl Iterator<Item = Interval<u64>> {
+        self.segments(range)
+            .into_iter()
+            .map(|(i, to)| Interval::from_len(to, i.len()))
+    }
 
-        let mut mapping = BTreeMap::new();
+    fn translate_set(&self, ranges: &IntervalSet<u64>) -> IntervalSet<u64> {
+        ranges
+            .iter()
+            .flat_map(|i| self.translate_range(i))
+            .collect()
+    }
 
-        for map in values.lines() {
-            let &[to, from, len] = map
-                .split_whitespace()
-                .map(str::parse::<u64>)
-                .collect::<Result<Vec<_>, _>>()?
-                .as_slice()
-            else {
-                eyre::bail!("Invalid mapping: {map}")
-            };
-            mapping.insert(Interval { start: from, len }, to);
+    /// Mapping equivalent to applying `self` then `next`
+    fn then(&self, next: &Mapping) -> Mapping {
+        let mut composed = BTreeMap::new();
+
+        for (from, to) in self.segments(Interval::new(0, u64::MAX)) {
+            let image = Interval::from_len(to, from.len());
+
+            for (image_piece, to) in next.segments(image) {
+                let from = Interval::from_len(
+                    from.start + (image_piece.start - image.start),
+                    image_piece.len(),
+                );
+
+                if from.start != to {
+                    composed.insert(from, to);
+                }
+            }
         }
 
-        Ok(mapping)
+        Mapping(composed)
     }
+}
 
-    Ok((
-        seeds,
-        Mappings {
-            seed_to_soil: parse_mapping(sections.by_ref(), "seed-to-soil")?,
-            soil_to_fertilizer: parse_mapping(sections.by_ref(), "soil-to-fertilizer")?,
-            fertilizer_to_water: parse_mapping(sections.by_ref(), "fertilizer-to-water")?,
-            water_to_light: parse_mapping(sections.by_ref(), "water-to-light")?,
-            light_to_temperature: parse_mapping(sections.by_ref(), "light-to-temperature")?,
-            temperature_to_humidity: parse_mapping(sections.by_ref(), "temperature-to-humidity")?,
-            humidity_to_location: parse_mapping(sections.by_ref(), "humidity-to-location")?,
-        },
-    ))
+#[derive(Debug)]
+pub struct Almanac<'a> {
+    seeds: Vec<u64>,
+    /// Seeds read as pairs of start and length, which only part 2 requires to be valid
+    seed_ranges: Result<IntervalSet<u64>, ParseError>,
+    /// Mapping from a category, with the category it maps to
+    mappings: HashMap<&'a str, (&'a str, Mapping)>,
 }
 
-impl Mappings {
-    fn location(&self, seed: u64) -> u64 {
-        fn translate(mapping: &Mapping, element: u64) -> u64 {
-            match mapping
-                .iter()
-                .find(|(i, _)| i.start <= element && element < i.start + i.len)
-            {
-                Some((i, to)) => {
-                    let offset = element - i.start;
-                    to + offset
-                }
-                None => element,
+impl Almanac<'_> {
+    /// Composes every mapping needed to go from one category to the other
+    fn chain(&self, from: &str, to: &str) -> color_eyre::Result<Mapping> {
+        let mut current = from;
+        let mut chain = Mapping::default();
+        let mut steps = 0;
+
+        while current != to {
+            let (next, mapping) = self
+                .mappings
+                .get(current)
+                .ok_or_else(|| eyre!("No mapping from {current}, can't reach {to} from {from}"))?;
+
+            steps += 1;
+            if steps > self.mappings.len() {
+                eyre::bail!("Mappings loop without reaching {to} from {from}")
             }
-        }
 
-        let soil = translate(&self.seed_to_soil, seed);
-        let fertilizer = translate(&self.soil_to_fertilizer, soil);
-        let water = translate(&self.fertilizer_to_water, fertilizer);
-        let light = translate(&self.water_to_light, water);
-        let temperature = translate(&self.light_to_temperature, light);
-        let humidity = translate(&self.temperature_to_humidity, temperature);
+            chain = chain.then(mapping);
+            current = next;
+        }
 
-        translate(&self.humidity_to_location, humidity)
+        Ok(chain)
     }
+}
 
-    fn location_range(&self, interval: Interval) -> Vec<Interval> {
-        fn translate_range(mapping: &Mapping, range: Interval) -> Vec<Interval> {
-            let mut mappings = mapping.iter();
-            let mut mapped = Vec::new();
+type Parsed<'a> = Almanac<'a>;
 
-            let mut current = range.start;
-            let mut current_len = range.len;
+pub fn parsing(input: &BString) -> Result<Parsed<'_>, ParseError> {
+    let error = |span: &str, message: String| ParseError::at(input, span.as_bytes(), message);
+    let number = |n: &str| parse::from_str::<u64>(input, n.as_bytes());
+    let input = parse::utf8(input)?;
 
-            while current < range.start + range.len {
-                let Some((&map_interval, &to)) =
-                    mappings.by_ref().find(|(i, _)| i.start + i.len > current)
-                else {
-                    break;
-                };
+    let mut sections = input.split("\n\n");
+    let seeds_section = sections.next().unwrap_or_default();
+    let seed_spans = seeds_section
+        .strip_prefix("seeds:")
+        .ok_or_else(|| error(seeds_section, "Missing seeds section".into()))?
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    let seeds = seed_spans
+        .iter()
+        .map(|n| number(n))
+        .collect::<Result<Vec<u64>, _>>()?;
+    if seeds.is_empty() {
+        return Err(error(seeds_section, "No seeds".into()));
+    }
 
-                if current < map_interval.start {
-                    let until_len = std::cmp::min(map_interval.start - current, current_len);
+    let seed_ranges = match seed_spans.chunks_exact(2).remainder() {
+        [last] => Err(error(last, "Seed start without a length".into())),
+        _ => seeds
+            .chunks_exact(2)
+            .zip(seed_spans.chunks_exact(2))
+            .map(|(c, spans)| match c[0].checked_add(c[1]) {
+                Some(end) => Ok(Interval::new(c[0], end)),
+                None => Err(error(spans[1], "Seed range overflows".into())),
+            })
+            .collect(),
+    };
+
+    let mut mappings = HashMap::new();
+    // Line of the last line of the previous section
+    let mut last_line = seeds_section.lines().count();
+
+    for section in sections {
+        let header_line = last_line + 2;
+        last_line = header_line + section.lines().count() - 1;
+
+        let (header, values) = section
+            .split_once('\n')
+            .ok_or_else(|| error(section, "Missing mapping values".into()))?;
+
+        let (from, to) = header
+            .strip_suffix(" map:")
+            .and_then(|name| name.split_once("-to-"))
+            .ok_or_else(|| error(header, "Invalid section name".into()))?;
+
+        let mut ranges = Vec::new();
+
+        for (i, map) in values.lines().enumerate() {
+            let &[to, from, len] = map
+                .split_whitespace()
+                .map(number)
+                .collect::<Result<Vec<_>, _>>()?
+                .as_slice()
+            else {
+                return Err(error(map, "Invalid mapping, expected 3 numbers".into()));
+            };
 
-                    mapped.push(Interval {
-                        start: current,
-                        len: until_len,
-                    });
+            if from.checked_add(len).is_none() || to.checked_add(len).is_none() {
+                return Err(error(map, "Mapping overflows".into()));
+            }
 
-                    current += until_len;
-                    current_len -= until_len;
-                }
+            if len != 0 {
+                ranges.push((header_line + 1 + i, map, Interval::from_len(from, len), to));
+            }
+        }
 
-                if current_len > 0 {
-                    assert!(current >= map_interval.start);
+        ranges.sort_unstable_by_key(|&(_, _, from, _)| from);
 
-                    let offset = current - map_interval.start;
-                    let len =
-                        std::cmp::min(map_interval.start + map_interval.len - current, current_len);
+        for w in ranges.windows(2) {
+            let [(first_line, _, first, _), (_, map, second, _)] = *w else {
+                unreachable!()
+            };
 
-                    mapped.push(Interval {
-                        start: to + offset,
-                        len,
-                    });
-                    current += len;
-                    current_len -= len;
-                }
+            if first.overlaps(&second) {
+                return Err(error(
+                    map,
+                    format!("{from} range {second} overlaps {first} from line {first_line}"),
+                ));
             }
+        }
 
-            if current_len > 0 {
-                mapped.push(Interval {
-                    start: current,
-                    len: current_len,
-                });
+        // Adjacent ranges continuing each other (present in the puzzle inputs) are merged
+        let mut mapping: BTreeMap<Interval<u64>, u64> = BTreeMap::new();
+        let mut previous_line = 0;
+        for (line, map, range, to) in ranges {
+            match mapping.pop_last() {
+                Some((last, last_to)) if last.end == range.start && last_to + last.len() == to => {
+                    let warning = error(
+                        map,
+                        format!("{from} range {range} continues the one on line {previous_line}, merging them"),
+                    );
+                    eprintln!("Warning: {warning}");
+                    mapping.insert(Interval::new(last.start, range.end), last_to);
+                }
+                last => {
+                    mapping.extend(last);
+                    mapping.insert(range, to);
+                }
             }
-
-            mapped
+            previous_line = line;
         }
 
-        fn translate_range_list(mapping: &Mapping, ranges: &[Interval]) -> Vec<Interval> {
-            ranges
-                .iter()
-                .flat_map(|&i| translate_range(mapping, i))
-                .collect()
+        if mappings.insert(from, (to, Mapping(mapping))).is_some() {
+            return Err(error(header, format!("Multiple mappings from {from}")));
         }
+    }
 
-        let soil = translate_range(&self.seed_to_soil, interval);
-        let fertilizer = translate_range_list(&self.soil_to_fertilizer, &soil);
-        let water = translate_range_list(&self.fertilizer_to_water, &fertilizer);
-        let light = translate_range_list(&self.water_to_light, &water);
-        let temperature = translate_range_list(&self.light_to_temperature, &light);
-        let humidity = translate_range_list(&self.temperature_to_humidity, &temperature);
+    Ok(Almanac {
+        seeds,
+        seed_ranges,
+        mappings,
+    })
+}
+
+/// Prints the listed values of the source category that give `value`
+fn reverse(mapping: &Mapping, value: u64, options: &Options, listed: impl Fn(u64) -> bool) {
+    let Options { from, to, .. } = options;
 
-        translate_range_list(&self.humidity_to_location, &humidity)
+    let sources = mapping
+        .preimages(value)
+        .into_iter()
+        .filter(|&v| listed(v))
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+
+    match sources.is_empty() {
+        true => print_res!("No {from} gives {to} {value}"),
+        false => print_res!("{to} {value} is given by {from}: {}", sources.join(", ")),
     }
 }
 
-pub fn part1((seeds, mappings): Parsed) {
-    let min_location = seeds.iter().map(|&s| mappings.location(s)).min().unwrap();
-    print_res!("Min location: {min_location}")
-}
+pub fn part1(almanac: Parsed, mapping: Mapping, options: &Options) {
+    if let Some(value) = options.reverse {
+        return reverse(&mapping, value, options, |v| almanac.seeds.contains(&v));
+    }
 
-pub fn part2((seeds, mappings): Parsed) {
-    let seed_ranges = seeds
-        .chunks_exact(2)
-        .map(|c| Interval {
-            start: c[0],
-            len: c[1],
-        })
-        .flat_map(|i| mappings.location_range(i).into_iter())
+    let min = almanac
+        .seeds
+        .iter()
+        .map(|&s| mapping.translate(s))
         .min()
         .unwrap();
-    print_res!("Min location with ranges: {}", seed_ranges.start)
+    print_res!("Min {}: {min}", options.to)
+}
+
+pub fn part2(almanac: Parsed, mapping: Mapping, options: &Options) -> color_eyre::Result<()> {
+    let seed_ranges = almanac.seed_ranges?;
+
+    if let Some(value) = options.reverse {
+        reverse(&mapping, value, options, |v| seed_ranges.contains(v));
+        return Ok(());
+    }
+
+    match mapping.translate_set(&seed_ranges).min() {
+        Some(min) => print_res!("Min {} with ranges: {min}", options.to),
+        None => print_res!("No {} with ranges, all seed ranges are empty", options.to),
+    }
+
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_with::<Options>()?;
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
+    let mapping = parsed.chain(&context.args.from, &context.args.to)?;
     let elapsed = humantime::format_duration(start.elapsed());
 
     let start = Instant::now();
     if context.part == 1 {
-        part1(parsed);
+        part1(parsed, mapping, &context.args);
     } else {
-        part2(parsed);
+        part2(parsed, mapping, &context.args)?;
     }
     let elapsed_part = humantime::format_duration(start.elapsed());
 
@@ -1,8 +1,14 @@
-use std::{ops::RangeInclusive, time::Instant};
-
-use aoc_2023::{load, print_res};
+use std::time::Instant;
+
+use aoc_2023::{
+    interval::Interval,
+    load,
+    parse::{self, ParseError},
+    print_res,
+};
 use bstr::BString;
 use color_eyre::eyre::eyre;
+use itertools::Itertools;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Race {
@@ -12,78 +18,125 @@ pub struct Race {
 
 type Parsed = Vec<Race>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    let input = std::str::from_utf8(input)?;
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    let error = |span: &str, message| ParseError::at(input, span.as_bytes(), message);
+    let number = |n: &str| parse::from_str(input, n.as_bytes());
+    let input = parse::utf8(input)?;
+
     let (time, distance) = input
         .split_once('\n')
-        .ok_or_else(|| eyre!("Malformed input {input}"))?;
+        .ok_or_else(|| error(input, "Missing distance line"))?;
     let time = time
         .strip_prefix("Time:")
-        .ok_or_else(|| eyre!("Malformed time: {time}"))?
-        .trim();
+        .ok_or_else(|| error(time, "Line not starting with 'Time:'"))?;
     let distance = distance
         .strip_prefix("Distance:")
-        .ok_or_else(|| eyre!("Malformed distance: {distance}"))?
-        .trim();
+        .ok_or_else(|| error(distance, "Line not starting with 'Distance:'"))?;
+
+    let (times, distances) = (
+        time.split_whitespace().collect_vec(),
+        distance.split_whitespace().collect_vec(),
+    );
+    if times.len() != distances.len() {
+        return Err(error(
+            distance,
+            &format!("Expected {} distances, one per time", times.len()),
+        ));
+    }
 
-    time.split_whitespace()
-        .zip(distance.split_whitespace())
+    times
+        .into_iter()
+        .zip(distances)
         .map(|(duration, record)| {
             Ok(Race {
-                duration: duration.parse()?,
-                record: record.parse()?,
+                duration: number(duration)?,
+                record: number(record)?,
             })
         })
         .collect()
 }
 
-impl Race {
-    fn winning_charges(&self) -> RangeInclusive<u64> {
-        let d_r = self.record as f64;
-        let t_r = self.duration as f64;
+/// Largest `r` such that `r * r <= n`
+fn isqrt(n: u128) -> u128 {
+    // The square root of a `u128` always fits in a `u64`, even if the float estimate rounds up
+    let mut r = std::cmp::min((n as f64).sqrt() as u128, u64::MAX as u128);
+
+    // The float estimate can be off by a few units for large values
+    while r.checked_mul(r).is_none_or(|square| square > n) {
+        r -= 1;
+    }
+    while (r + 1).checked_mul(r + 1).is_some_and(|square| square <= n) {
+        r += 1;
+    }
 
-        let delta = t_r * t_r - 4. * d_r;
+    r
+}
 
-        if delta < 0. {
-            panic!("Race {self:?} can't be won")
-        }
+impl Race {
+    fn distance(&self, charge: u64) -> u128 {
+        charge as u128 * (self.duration - charge) as u128
+    }
 
-        let max = ((t_r + delta.sqrt()) / 2.).floor() as u64;
-        let min = ((t_r - delta.sqrt()) / 2.).max(0.).ceil() as u64;
+    /// Charge going the furthest, with the distance it reaches
+    fn best_charge(&self) -> (u64, u128) {
+        let charge = self.duration / 2;
+        (charge, self.distance(charge))
+    }
 
-        let distance = |charge: u64| (self.duration - charge) * charge;
+    /// Charges beating the record, empty if the race can't be won
+    fn winning_charges(&self) -> Interval<u64> {
+        let (best, max_distance) = self.best_charge();
+        if max_distance <= self.record as u128 {
+            return Interval::empty();
+        }
 
-        let max = if distance(max) <= self.record {
-            max - 1
-        } else {
-            max
-        };
+        // Roots of `c * (duration - c) = record`
+        let duration = self.duration as u128;
+        let delta = duration * duration - 4 * self.record as u128;
+        let mut min = ((duration - isqrt(delta)) / 2) as u64;
 
-        let min = if distance(min) <= self.record {
-            min + 1
-        } else {
-            min
-        };
+        while self.distance(min) <= self.record as u128 {
+            min += 1;
+        }
+        while min > 0 && self.distance(min - 1) > self.record as u128 {
+            min -= 1;
+        }
+        debug_assert!(min <= best);
 
-        min..=max
+        // The distance is symmetric around the best charge
+        Interval::inclusive(min, self.duration - min)
     }
 }
 
-pub fn part1(input: Parsed) {
-    let possible_winning_charges_product: u64 = input
+pub fn part1(input: Parsed) -> color_eyre::Result<()> {
+    let possible_winning_charges_product = input
         .iter()
-        .map(Race::winning_charges)
-        .map(|r| r.end() - r.start() + 1)
-        .product();
+        .try_fold(1u64, |product, r| {
+            product.checked_mul(r.winning_charges().len())
+        })
+        .ok_or_else(|| eyre!("Product of winning charges is too large"))?;
     print_res!("Product of wining charges: {possible_winning_charges_product}");
+    Ok(())
 }
 
-pub fn part2(input: Parsed) {
+/// Appends the digits of `b` to `a`, `None` if it overflows
+fn concat(a: u64, b: u64) -> Option<u64> {
+    if a == 0 {
+        return Some(b);
+    }
+
+    let digits = b.checked_ilog10().unwrap_or(0) + 1;
+    a.checked_mul(10u64.checked_pow(digits)?)?.checked_add(b)
+}
+
+pub fn part2(input: Parsed) -> color_eyre::Result<()> {
     let mut total_time = 0;
     let mut total_distance = 0;
     for race in input {
-        total_time = total_time * 10u64.pow(race.duration.ilog10() + 1) + race.duration;
-        total_distance = total_distance * 10u64.pow(race.record.ilog10() + 1) + race.record;
+        total_time = concat(total_time, race.duration)
+            .ok_or_else(|| eyre!("Concatenated race is too long"))?;
+        total_distance = concat(total_distance, race.record)
+            .ok_or_else(|| eyre!("Concatenated race is too long"))?;
     }
 
     let single_race = Race {
@@ -91,10 +144,13 @@ pub fn part2(input: Parsed) {
         duration: total_time,
     };
 
-    let winning_range = single_race.winning_charges();
-    let ways_to_win = winning_range.end() - winning_range.start() + 1;
+    let (best, max_distance) = single_race.best_charge();
+    print_res!("Best charge: {best}, going {max_distance}");
+
+    let ways_to_win = single_race.winning_charges().len();
 
     print_res!("Number of ways the race can be won: {ways_to_win}");
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
@@ -106,9 +162,9 @@ pub fn main() -> color_eyre::Result<()> {
 
     let start = Instant::now();
     if context.part == 1 {
-        part1(parsed);
+        part1(parsed)?;
     } else {
-        part2(parsed);
+        part2(parsed)?;
     }
     let elapsed_part = humantime::format_duration(start.elapsed());
 
@@ -117,3 +173,54 @@ pub fn main() -> color_eyre::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn isqrt_small(n in 0u128..100_000) {
+            let brute = (0..).take_while(|r| r * r <= n).last().unwrap();
+            prop_assert_eq!(isqrt(n), brute);
+        }
+
+        #[test]
+        fn isqrt_large(n in any::<u128>()) {
+            let r = isqrt(n);
+            prop_assert!(r * r <= n);
+            prop_assert!((r + 1).checked_mul(r + 1).is_none_or(|square| square > n));
+        }
+
+        #[test]
+        fn winning_charges(duration in 0u64..200, record in 0u64..12_000) {
+            let race = Race { duration, record };
+            let brute = (0..=duration)
+                .filter(|&c| race.distance(c) > record as u128)
+                .collect::<Vec<_>>();
+            let charges = race.winning_charges();
+
+            prop_assert_eq!(charges.len(), brute.len() as u64);
+            if let (Some(&first), Some(&last)) = (brute.first(), brute.last()) {
+                prop_assert_eq!(charges, Interval::inclusive(first, last));
+            }
+        }
+    }
+
+    #[test]
+    fn isqrt_extremes() {
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+        assert_eq!(isqrt((u64::MAX as u128).pow(2)), u64::MAX as u128);
+        assert_eq!(isqrt((u64::MAX as u128).pow(2) - 1), u64::MAX as u128 - 1);
+    }
+
+    #[test]
+    fn longest_race() {
+        let race = Race {
+            duration: u64::MAX,
+            record: 0,
+        };
+        assert_eq!(race.winning_charges(), Interval::inclusive(1, u64::MAX - 1));
+    }
+}
@@ -1,11 +1,32 @@
 use std::time::Instant;
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{
+    load_streamable,
+    parse::{self, ParseError},
+    print_res,
+    stream::Records,
+};
 use bstr::{BString, ByteSlice};
-use color_eyre::eyre::{self, eyre};
+use color_eyre::eyre;
 use enum_map::{Enum, EnumMap};
 use itertools::Itertools;
 
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Cards from the strongest to the weakest, like `AKQJT98765432`
+    #[arg(long)]
+    order: Option<String>,
+    /// Cards acting as whichever card makes the best hand, none with an empty string
+    #[arg(long)]
+    wild: Option<String>,
+    /// How hands of the same kind are ranked
+    #[arg(long, value_enum)]
+    tie_break: Option<TieBreak>,
+    /// Print how each hand was classified & ranked
+    #[arg(long)]
+    explain: bool,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Enum)]
 pub enum Card {
     Ace,
@@ -23,265 +44,385 @@ pub enum Card {
     Two,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Enum)]
-pub enum JokerCard {
-    Ace,
-    King,
-    Queen,
-    Ten,
-    Nine,
-    Eight,
-    Seven,
-    Six,
-    Five,
-    Four,
-    Three,
-    Two,
-    Joker,
-}
+impl TryFrom<u8> for Card {
+    type Error = color_eyre::Report;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum Hand {
-    Five,
-    Four,
-    FullHouse,
-    Three,
-    TwoPair,
-    OnePair,
-    HighCard,
+    fn try_from(card: u8) -> Result<Self, Self::Error> {
+        Ok(match card {
+            b'A' => Card::Ace,
+            b'K' => Card::King,
+            b'Q' => Card::Queen,
+            b'J' => Card::Jack,
+            b'T' => Card::Ten,
+            b'9' => Card::Nine,
+            b'8' => Card::Eight,
+            b'7' => Card::Seven,
+            b'6' => Card::Six,
+            b'5' => Card::Five,
+            b'4' => Card::Four,
+            b'3' => Card::Three,
+            b'2' => Card::Two,
+            _ => eyre::bail!("Invalid card: {}", card as char),
+        })
+    }
 }
 
-impl From<Card> for JokerCard {
-    fn from(value: Card) -> Self {
-        match value {
-            Card::Ace => JokerCard::Ace,
-            Card::King => JokerCard::King,
-            Card::Queen => JokerCard::Queen,
-            Card::Jack => JokerCard::Joker,
-            Card::Ten => JokerCard::Ten,
-            Card::Nine => JokerCard::Nine,
-            Card::Eight => JokerCard::Eight,
-            Card::Seven => JokerCard::Seven,
-            Card::Six => JokerCard::Six,
-            Card::Five => JokerCard::Five,
-            Card::Four => JokerCard::Four,
-            Card::Three => JokerCard::Three,
-            Card::Two => JokerCard::Two,
+impl Card {
+    fn symbol(self) -> char {
+        match self {
+            Card::Ace => 'A',
+            Card::King => 'K',
+            Card::Queen => 'Q',
+            Card::Jack => 'J',
+            Card::Ten => 'T',
+            Card::Nine => '9',
+            Card::Eight => '8',
+            Card::Seven => '7',
+            Card::Six => '6',
+            Card::Five => '5',
+            Card::Four => '4',
+            Card::Three => '3',
+            Card::Two => '2',
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Draw([Card; 5]);
+fn parse_cards(cards: &[u8]) -> color_eyre::Result<Vec<Card>> {
+    cards.iter().map(|&c| Card::try_from(c)).collect()
+}
+
+/// Kind of a hand, as the sizes of its groups of identical cards from the largest one
+///
+/// Comparing the sizes in order ranks the hands as usual: five of a kind, four of a kind, full
+/// house, ...
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hand(Vec<usize>);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct JokerDraw([JokerCard; 5]);
+impl std::fmt::Display for Hand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let singles = |groups: &[usize]| groups.iter().all(|&g| g == 1);
 
-impl From<Draw> for JokerDraw {
-    fn from(value: Draw) -> Self {
-        let [a, b, c, d, e] = value.0;
-        Self([a.into(), b.into(), c.into(), d.into(), e.into()])
+        match self.0.as_slice() {
+            [1, ..] => write!(f, "high card"),
+            [2, rest @ ..] if singles(rest) => write!(f, "one pair"),
+            [2, 2, rest @ ..] if singles(rest) => write!(f, "two pair"),
+            [3, 2, rest @ ..] if singles(rest) => write!(f, "full house"),
+            [n, rest @ ..] if singles(rest) => write!(f, "{n} of a kind"),
+            groups => write!(f, "groups of {}", groups.iter().join("+")),
+        }
     }
 }
 
-type Parsed = Vec<(Draw, usize)>;
+/// How a draw was classified
+#[derive(Debug)]
+struct Classification {
+    /// Number of each card, wildcards included
+    counts: EnumMap<Card, usize>,
+    /// Card the wildcards stand for, if there are any
+    substitute: Option<Card>,
+    hand: Hand,
+}
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    input
-        .lines()
-        .map(|l| {
-            let (cards, bid) = l
-                .split_once_str(" ")
-                .ok_or_else(|| eyre!("Malformed line: {}", l.as_bstr()))?;
-
-            eyre::ensure!(cards.len() == 5, "Must be exactly five cards");
-
-            let mut parsed_cards = [Card::Ace; 5];
-            for (&card, parsed) in cards.iter().zip(parsed_cards.iter_mut()) {
-                *parsed = match card {
-                    b'A' => Card::Ace,
-                    b'K' => Card::King,
-                    b'Q' => Card::Queen,
-                    b'J' => Card::Jack,
-                    b'T' => Card::Ten,
-                    b'9' => Card::Nine,
-                    b'8' => Card::Eight,
-                    b'7' => Card::Seven,
-                    b'6' => Card::Six,
-                    b'5' => Card::Five,
-                    b'4' => Card::Four,
-                    b'3' => Card::Three,
-                    b'2' => Card::Two,
-                    _ => eyre::bail!("Invalid card: {}", card as char),
-                };
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TieBreak {
+    /// Compare the cards in the order they were drawn
+    Positional,
+    /// Compare the largest groups first, like a poker kicker
+    Kicker,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ruleset {
+    /// Strength of each card, the strongest being the largest
+    strength: EnumMap<Card, u8>,
+    wildcard: EnumMap<Card, bool>,
+    tie_break: TieBreak,
+}
 
-            let bid = std::str::from_utf8(bid)?.parse()?;
+impl Ruleset {
+    fn new(order: &[Card], wildcards: &[Card], tie_break: TieBreak) -> color_eyre::Result<Self> {
+        let mut strength = EnumMap::default();
+        let mut seen = EnumMap::<Card, bool>::default();
+        for (i, &card) in order.iter().enumerate() {
+            eyre::ensure!(!seen[card], "Card {card:?} is ordered twice");
+            seen[card] = true;
+            strength[card] = (order.len() - i) as u8;
+        }
+
+        if let Some((card, _)) = seen.iter().find(|(_, &s)| !s) {
+            eyre::bail!("Card {card:?} is missing from the order");
+        }
 
-            Ok((Draw(parsed_cards), bid))
+        let mut wildcard = EnumMap::default();
+        for &card in wildcards {
+            wildcard[card] = true;
+        }
+
+        Ok(Self {
+            strength,
+            wildcard,
+            tie_break,
         })
-        .collect()
-}
+    }
+
+    /// Rules of the first part
+    fn standard() -> Self {
+        Self::new(
+            &parse_cards(b"AKQJT98765432").unwrap(),
+            &[],
+            TieBreak::Positional,
+        )
+        .unwrap()
+    }
+
+    /// Rules of the second part, where jacks become jokers
+    fn jokers() -> Self {
+        Self::new(
+            &parse_cards(b"AKQT98765432J").unwrap(),
+            &[Card::Jack],
+            TieBreak::Positional,
+        )
+        .unwrap()
+    }
+
+    fn customize(self, options: &Options) -> color_eyre::Result<Self> {
+        let order = match &options.order {
+            Some(order) => parse_cards(order.as_bytes())?,
+            None => self
+                .strength
+                .iter()
+                .sorted_by_key(|&(_, &s)| std::cmp::Reverse(s))
+                .map(|(c, _)| c)
+                .collect(),
+        };
 
-impl Draw {
-    fn hand(&self) -> Hand {
-        let mut map: EnumMap<Card, usize> = EnumMap::default();
-        for card in self.0 {
-            map[card] += 1;
+        let wildcards = match &options.wild {
+            Some(wild) => parse_cards(wild.as_bytes())?,
+            None => self
+                .wildcard
+                .iter()
+                .filter(|&(_, &w)| w)
+                .map(|(c, _)| c)
+                .collect(),
+        };
+
+        Self::new(
+            &order,
+            &wildcards,
+            options.tie_break.unwrap_or(self.tie_break),
+        )
+    }
+
+    fn classify(&self, draw: &Draw) -> Classification {
+        let mut counts: EnumMap<Card, usize> = EnumMap::default();
+        for &card in &draw.0 {
+            counts[card] += 1;
         }
 
-        let largest_similar = map.values().max().unwrap();
-        match largest_similar {
-            5 => Hand::Five,
-            4 => Hand::Four,
-            3 => {
-                if map.values().any(|&v| v == 2) {
-                    Hand::FullHouse
-                } else {
-                    Hand::Three
-                }
-            }
-            2 => {
-                if map.values().filter(|&&v| v == 2).count() == 2 {
-                    Hand::TwoPair
-                } else {
-                    Hand::OnePair
+        let (wild, natural): (Vec<_>, Vec<_>) = counts
+            .iter()
+            .filter(|&(_, &n)| n != 0)
+            .map(|(c, &n)| (c, n))
+            .partition(|&(c, _)| self.wildcard[c]);
+        let wildcards: usize = wild.iter().map(|&(_, n)| n).sum();
+
+        let mut groups = natural
+            .iter()
+            .map(|&(_, n)| n)
+            .sorted_unstable()
+            .rev()
+            .collect_vec();
+
+        // Wildcards are always best used to grow the largest group, choosing its strongest card
+        let substitute = (wildcards != 0).then(|| {
+            natural
+                .iter()
+                .max_by_key(|&&(c, n)| (n, self.strength[c]))
+                .map(|&(c, _)| c)
+                .unwrap_or_else(|| self.strongest())
+        });
+        match groups.first_mut() {
+            Some(largest) => *largest += wildcards,
+            None => groups.push(wildcards),
+        }
+
+        Classification {
+            counts,
+            substitute,
+            hand: Hand(groups),
+        }
+    }
+
+    fn strongest(&self) -> Card {
+        self.strength
+            .iter()
+            .max_by_key(|&(_, &s)| s)
+            .map(|(c, _)| c)
+            .unwrap()
+    }
+
+    /// Strengths of the cards, in the order they are compared when the hands are of the same kind
+    fn tie_break(&self, draw: &Draw) -> Vec<u8> {
+        match self.tie_break {
+            TieBreak::Positional => draw.0.iter().map(|&c| self.strength[c]).collect(),
+            TieBreak::Kicker => {
+                let mut counts: EnumMap<Card, usize> = EnumMap::default();
+                for &card in &draw.0 {
+                    counts[card] += 1;
                 }
+
+                draw.0
+                    .iter()
+                    .map(|&c| (!self.wildcard[c], counts[c], self.strength[c]))
+                    .sorted_unstable()
+                    .rev()
+                    .map(|(_, _, s)| s)
+                    .collect()
             }
-            1 => Hand::HighCard,
-            _ => unreachable!(),
         }
     }
-}
 
-impl PartialOrd for Draw {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    fn key(&self, draw: &Draw) -> (Hand, Vec<u8>) {
+        (self.classify(draw).hand, self.tie_break(draw))
     }
-}
 
-impl Ord for Draw {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.hand().cmp(&other.hand()).then(self.0.cmp(&other.0))
+    fn explain(&self, draw: &Draw) -> String {
+        let Classification {
+            counts,
+            substitute,
+            hand,
+        } = self.classify(draw);
+
+        let counts = counts
+            .iter()
+            .filter(|&(_, &n)| n != 0)
+            .sorted_by_key(|&(c, &n)| std::cmp::Reverse((n, self.strength[c])))
+            .map(|(c, n)| format!("{n}x{}", c.symbol()))
+            .join(" ");
+
+        let substitution = match substitute {
+            Some(card) => {
+                let wild = draw.0.iter().filter(|&&c| self.wildcard[c]);
+                format!(
+                    ", {} as {}",
+                    wild.map(|c| c.symbol()).unique().join("/"),
+                    card.symbol()
+                )
+            }
+            None => String::new(),
+        };
+
+        format!("{hand} ({counts}{substitution})")
     }
 }
 
-pub fn part1(input: Parsed) {
-    let total: usize = input
-        .iter()
-        .sorted_by_key(|(a, _)| a)
-        .rev()
-        .enumerate()
-        .map(|(i, (_, b))| (i + 1) * b)
-        .sum();
-    print_res!("Total winnings: {total}")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Draw(Vec<Card>);
+
+impl std::fmt::Display for Draw {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|c| write!(f, "{}", c.symbol()))
+    }
 }
 
-impl JokerDraw {
-    fn hand(&self) -> Hand {
-        let mut map: EnumMap<JokerCard, usize> = EnumMap::default();
+type Parsed = Vec<(Draw, usize)>;
 
-        for card in self.0 {
-            map[card] += 1;
-        }
+/// Parses the draw on line `i`, its hand having as many cards as the ones in `draws`
+fn parse_draw(i: usize, line: &[u8], draws: &Parsed) -> Result<(Draw, usize), ParseError> {
+    let error = |span: &[u8], message: String| ParseError::at(line, span, message).on_line(i + 1);
 
-        let largest_similar = map
-            .iter()
-            .filter_map(|(c, v)| match c {
-                JokerCard::Joker => None,
-                _ => Some(v),
-            })
-            .max()
-            .unwrap();
-        let joker_count = map[JokerCard::Joker];
-
-        match largest_similar {
-            5 | 0 => Hand::Five,
-            4 => match joker_count {
-                1 => Hand::Five,
-                0 => Hand::Four,
-                _ => unreachable!(),
-            },
-            3 => match joker_count {
-                2 => Hand::Five,
-                1 => Hand::Four,
-                0 => {
-                    if map.values().any(|&v| v == 2) {
-                        Hand::FullHouse
-                    } else {
-                        Hand::Three
-                    }
-                }
-                _ => unreachable!(),
-            },
-            2 => match joker_count {
-                3 => Hand::Five,
-                2 => Hand::Four,
-                1 => {
-                    if map.values().filter(|&&v| v == 2).count() == 2 {
-                        Hand::FullHouse
-                    } else {
-                        Hand::Three
-                    }
-                }
-                0 => {
-                    if map.values().filter(|&&v| v == 2).count() == 2 {
-                        Hand::TwoPair
-                    } else {
-                        Hand::OnePair
-                    }
-                }
-                _ => unreachable!(),
-            },
-            1 => match joker_count {
-                4 => Hand::Five,
-                3 => Hand::Four,
-                2 => Hand::Three,
-                1 => Hand::OnePair,
-                0 => Hand::HighCard,
-                _ => unreachable!(),
-            },
-            _ => unreachable!(),
+    let (cards, bid) = line
+        .split_once_str(" ")
+        .ok_or_else(|| error(line, "Missing ' ' between the hand and the bid".into()))?;
+
+    if cards.is_empty() {
+        return Err(error(cards, "Hands can't be empty".into()));
+    }
+    if let Some((first, _)) = draws.first() {
+        if cards.len() != first.0.len() {
+            return Err(error(
+                cards,
+                format!("All hands must have {} cards", first.0.len()),
+            ));
         }
     }
+
+    let cards = cards
+        .iter()
+        .enumerate()
+        .map(|(x, &c)| Card::try_from(c).map_err(|e| error(&cards[x..=x], e.to_string())))
+        .try_collect()?;
+    let bid = parse::from_str(line, bid).map_err(|e| e.on_line(i + 1))?;
+
+    Ok((Draw(cards), bid))
 }
 
-impl PartialOrd for JokerDraw {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    let mut draws = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        draws.push(parse_draw(i, line, &draws)?);
     }
+    Ok(draws)
 }
 
-impl Ord for JokerDraw {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.hand().cmp(&other.hand()).then(self.0.cmp(&other.0))
-    }
+/// Ranking needs every hand, so only the text of the input is not kept in memory
+pub fn stream_parsing(records: Records) -> color_eyre::Result<Parsed> {
+    let mut draws = Vec::new();
+    records.try_for_each(|i, line| {
+        draws.push(parse_draw(i, line, &draws)?);
+        Ok(())
+    })?;
+    Ok(draws)
 }
 
-pub fn part2(input: Parsed) {
+fn total_winnings(input: Parsed, rules: &Ruleset, explain: bool) {
     let total: usize = input
         .iter()
-        .map(|&(d, b)| (JokerDraw::from(d), b))
-        .sorted_by_key(|&(a, _)| a)
-        .rev()
+        .sorted_by_cached_key(|(d, _)| rules.key(d))
         .enumerate()
-        .map(|(i, (_, b))| (i + 1) * b)
+        .map(|(i, (draw, bid))| {
+            let winnings = (i + 1) * bid;
+            if explain {
+                print_res!(
+                    "rank {:>4}: {draw} bid {bid:>4} wins {winnings:>7}, {}",
+                    i + 1,
+                    rules.explain(draw)
+                );
+            }
+            winnings
+        })
         .sum();
     print_res!("Total winnings: {total}")
 }
 
+pub fn part1(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    let rules = Ruleset::standard().customize(options)?;
+    total_winnings(input, &rules, options.explain);
+    Ok(())
+}
+
+pub fn part2(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    let rules = Ruleset::jokers().customize(options)?;
+    total_winnings(input, &rules, options.explain);
+    Ok(())
+}
+
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_streamable::<Options>()?;
+    let options = &context.args.args;
 
     let start = Instant::now();
-    let parsed = parsing(&context.input)?;
+    let parsed = match context.args.stream {
+        true => stream_parsing(context.records(b'\n')?)?,
+        false => parsing(&context.input)?,
+    };
     let elapsed = humantime::format_duration(start.elapsed());
 
     let start = Instant::now();
     if context.part == 1 {
-        part1(parsed);
+        part1(parsed, options)?;
     } else {
-        part2(parsed);
+        part2(parsed, options)?;
     }
     let elapsed_part = humantime::format_duration(start.elapsed());
 
@@ -1,8 +1,7 @@
 use std::{collections::HashMap, time::Instant};
 
-use aoc_2023::{load, print_res};
+use aoc_2023::{load, parse::ParseError, print_res};
 use bstr::{BStr, BString, ByteSlice};
-use color_eyre::eyre::eyre;
 use itertools::Itertools;
 
 #[derive(Debug)]
@@ -13,37 +12,55 @@ pub enum Direction {
 
 type Parsed<'a> = (Vec<Direction>, HashMap<&'a BStr, (&'a BStr, &'a BStr)>);
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
+pub fn parsing(input: &BString) -> Result<Parsed<'_>, ParseError> {
+    let error = |span: &[u8], message: &str| ParseError::at(input, span, message);
+
     let (directions, map) = input
         .split_once_str("\n\n")
-        .ok_or_else(|| eyre!("Missing separator in input"))?;
+        .ok_or_else(|| error(input, "Missing empty line after the directions"))?;
 
+    if directions.is_empty() {
+        return Err(error(directions, "No directions"));
+    }
     let directions = directions
         .iter()
-        .map(|&b| match b {
+        .enumerate()
+        .map(|(i, &b)| match b {
             b'L' => Ok(Direction::Left),
             b'R' => Ok(Direction::Right),
-            _ => Err(eyre!("Invalid direction: {}", b as char)),
+            _ => Err(error(&directions[i..=i], "Invalid direction")),
         })
         .try_collect()?;
 
-    let map = map
-        .lines()
-        .map(|line| -> color_eyre::Result<_> {
-            let (from, to) = line
-                .split_once_str(" = ")
-                .ok_or_else(|| eyre!("Malformed line: {}", line.as_bstr()))?;
-
-            let (left, right) = to
-                .trim_with(|c| c == '(' || c == ')')
-                .split_once_str(", ")
-                .ok_or_else(|| eyre!("Malformed dest: {}", to.as_bstr()))?;
+    let mut nodes = HashMap::new();
+    let mut destinations = Vec::new();
+    for line in map.lines() {
+        let (from, to) = line
+            .split_once_str(" = ")
+            .ok_or_else(|| error(line, "Missing ' = ' after the node"))?;
+
+        let (left, right) = to
+            .strip_prefix(b"(")
+            .and_then(|to| to.strip_suffix(b")"))
+            .and_then(|to| to.split_once_str(", "))
+            .ok_or_else(|| error(to, "Destinations are not like '(left, right)'"))?;
+
+        if nodes
+            .insert(from.as_bstr(), (left.as_bstr(), right.as_bstr()))
+            .is_some()
+        {
+            return Err(error(from, "Node is defined twice"));
+        }
+        destinations.extend([left, right]);
+    }
 
-            Ok((from.as_bstr(), (left.as_bstr(), right.as_bstr())))
-        })
-        .try_collect()?;
+    for to in destinations {
+        if !nodes.contains_key(to.as_bstr()) {
+            return Err(error(to, "Node is not defined"));
+        }
+    }
 
-    Ok((directions, map))
+    Ok((directions, nodes))
 }
 
 fn loop_len(directions: &[Direction], map: &HashMap<&BStr, (&BStr, &BStr)>, start: &BStr) -> usize {
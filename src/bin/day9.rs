@@ -1,92 +1,187 @@
-use std::{collections::VecDeque, time::Instant};
-
-use aoc_2023::{load, print_res};
-use bstr::BString;
-use color_eyre::eyre::{eyre, Context};
+use std::time::Instant;
+
+use aoc_2023::{
+    load_streamable,
+    parse::{self, ParseError},
+    print_res,
+    stream::Records,
+};
+use bstr::{BString, ByteSlice};
+use color_eyre::eyre::{bail, eyre, Context};
 use itertools::Itertools;
 
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// How many values after (part 1) or before (part 2) the sequences to extrapolate
+    #[arg(long, default_value_t = 1)]
+    steps: u32,
+}
+
 type Parsed = Vec<Vec<i64>>;
 
-pub fn parsing(input: &BString) -> color_eyre::Result<Parsed> {
-    std::str::from_utf8(input)?
+fn parse_sequence(i: usize, line: &[u8]) -> Result<Vec<i64>, ParseError> {
+    line.fields()
+        .map(|n| parse::from_str(line, n).map_err(|e| e.on_line(i + 1)))
+        .try_collect()
+}
+
+pub fn parsing(input: &BString) -> Result<Parsed, ParseError> {
+    input
         .lines()
         .enumerate()
-        .map(|(i, l)| {
-            l.split_whitespace()
-                .map(|n| n.parse().wrap_err(eyre!("In line {i}")))
-                .try_collect()
-        })
+        .map(|(i, l)| parse_sequence(i, l))
         .collect()
 }
 
-fn difference(a: &[i64]) -> impl Iterator<Item = i64> + '_ {
-    a.iter().tuple_windows().map(|(&a, &b)| b - a)
+/// Generalized binomial coefficient, `n` can be negative
+fn binomial(n: i128, k: i128) -> Option<i128> {
+    (0..k).try_fold(1i128, |c, i| c.checked_mul(n - i).map(|c| c / (i + 1)))
 }
 
-fn seqs_of_diffs(input: Vec<i64>) -> Vec<Vec<i64>> {
-    let mut sequences = vec![input];
-
-    loop {
-        let mut all_zero = true;
-        let diff_seq = difference(sequences.last().unwrap())
-            .inspect(|&n| all_zero &= n == 0)
-            .collect();
+/// Polynomial going through the values of a sequence, evaluated with Lagrange's formula
+struct Polynomial<'a> {
+    values: &'a [i64],
+}
 
-        if all_zero {
-            break sequences;
-        } else {
-            sequences.push(diff_seq);
+impl<'a> Polynomial<'a> {
+    /// Fails if the values are not on a polynomial of degree lower than their count minus one,
+    /// that is if the differences don't reach a zero row before the end
+    fn fit(values: &'a [i64]) -> color_eyre::Result<Self> {
+        let Some(degree) = values.len().checked_sub(1) else {
+            bail!("Empty sequence")
+        };
+
+        // Last row of the differences, with the values at alternating signs
+        let last_difference = values.iter().enumerate().try_fold(0i128, |acc, (i, &y)| {
+            let c = binomial(degree as i128, i as i128)?;
+            let c = if (degree - i) % 2 == 0 { c } else { -c };
+            acc.checked_add(c.checked_mul(y as i128)?)
+        });
+
+        match last_difference {
+            Some(0) => Ok(Self { values }),
+            Some(_) => bail!("Sequence does not reach a zero row of differences"),
+            None => bail!("Overflow while computing differences"),
         }
     }
-}
-
-pub fn part1(input: Parsed) {
-    let mut last_sum = 0;
 
-    for sequence in input {
-        let mut sequences = seqs_of_diffs(sequence);
-
-        let last = sequences.last_mut().unwrap();
-        last.push(last[0]);
+    /// Value of the polynomial at `x`, the values being at `0..len`
+    fn at(&self, x: i128) -> color_eyre::Result<i128> {
+        let n = self.values.len() as i128;
+        let overflow = || eyre!("Overflow while extrapolating at {x}");
+
+        // L_i(x) = (-1)^(n-1-i) * C(x, i) * C(x-i-1, n-1-i), the last factor computed from the
+        // end with (k+1) * C(a+1, k+1) = (a+1) * C(a, k)
+        let mut tails = vec![0; self.values.len()];
+        let mut tail = 1i128;
+        for i in (0..n).rev() {
+            tails[i as usize] = tail;
+            if i != 0 {
+                let (a, k) = (x - i - 1, n - 1 - i);
+                tail = tail.checked_mul(a + 1).ok_or_else(overflow)? / (k + 1);
+            }
+        }
 
-        for i in 0..(sequences.len() - 1) {
-            let idx = sequences.len() - 1 - i;
-            let &diff = sequences[idx].last().unwrap();
-            let &orig = sequences[idx - 1].last().unwrap();
-            sequences[idx - 1].push(diff + orig)
+        let mut head = 1i128;
+        let mut value = 0i128;
+        for (i, (&y, &tail)) in self.values.iter().zip(&tails).enumerate() {
+            let i = i as i128;
+            if i != 0 {
+                head = head.checked_mul(x - i + 1).ok_or_else(overflow)? / i;
+            }
+
+            let coefficient = head.checked_mul(tail).ok_or_else(overflow)?;
+            let coefficient = if (n - 1 - i) % 2 == 0 {
+                coefficient
+            } else {
+                -coefficient
+            };
+
+            value = coefficient
+                .checked_mul(y as i128)
+                .and_then(|t| value.checked_add(t))
+                .ok_or_else(overflow)?;
         }
 
-        last_sum += sequences[0].last().unwrap();
+        Ok(value)
     }
+}
 
-    print_res!("Sum of continuations is: {last_sum}");
+/// Value `steps` positions after the end of the sequence, or before its start when `backwards`
+fn extrapolate(
+    i: usize,
+    sequence: &[i64],
+    steps: u32,
+    backwards: bool,
+) -> color_eyre::Result<i128> {
+    let polynomial = Polynomial::fit(sequence).wrap_err(eyre!("In line {}", i + 1))?;
+    let x = match backwards {
+        false => sequence.len() as i128 - 1 + steps as i128,
+        true => -(steps as i128),
+    };
+
+    polynomial.at(x).wrap_err(eyre!("In line {}", i + 1))
 }
 
-pub fn part2(input: Parsed) {
-    let mut first_sum = 0;
+fn extrapolate_sum(input: &Parsed, steps: u32, backwards: bool) -> color_eyre::Result<i128> {
+    input
+        .iter()
+        .enumerate()
+        .try_fold(0i128, |sum, (i, sequence)| {
+            sum.checked_add(extrapolate(i, sequence, steps, backwards)?)
+                .ok_or_else(|| eyre!("Overflow while summing"))
+        })
+}
 
-    for sequence in input {
-        let sequences = seqs_of_diffs(sequence);
-        let mut sequences = sequences.into_iter().map(VecDeque::from).collect_vec();
+fn stream_extrapolate_sum(
+    records: Records,
+    steps: u32,
+    backwards: bool,
+) -> color_eyre::Result<i128> {
+    records.par_sum(|i, line| extrapolate(i, &parse_sequence(i, line)?, steps, backwards))
+}
 
-        let last = sequences.last_mut().unwrap();
-        last.push_front(last[0]);
+pub fn part1(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    let last_sum = extrapolate_sum(&input, options.steps, false)?;
+    print_res!("Sum of continuations is: {last_sum}");
+    Ok(())
+}
 
-        for i in 0..(sequences.len() - 1) {
-            let idx = sequences.len() - 1 - i;
-            let diff = sequences[idx][0];
-            let orig = sequences[idx - 1][0];
-            sequences[idx - 1].push_front(orig - diff)
-        }
+pub fn part2(input: Parsed, options: &Options) -> color_eyre::Result<()> {
+    let first_sum = extrapolate_sum(&input, options.steps, true)?;
+    print_res!("Sum of previsions is: {first_sum}");
+    Ok(())
+}
 
-        first_sum += sequences[0][0];
-    }
+pub fn stream_part1(records: Records, options: &Options) -> color_eyre::Result<()> {
+    let last_sum = stream_extrapolate_sum(records, options.steps, false)?;
+    print_res!("Sum of continuations is: {last_sum}");
+    Ok(())
+}
 
+pub fn stream_part2(records: Records, options: &Options) -> color_eyre::Result<()> {
+    let first_sum = stream_extrapolate_sum(records, options.steps, true)?;
     print_res!("Sum of previsions is: {first_sum}");
+    Ok(())
 }
 
 pub fn main() -> color_eyre::Result<()> {
-    let context = load()?;
+    let context = load_streamable::<Options>()?;
+    let options = &context.args.args;
+
+    if context.args.stream {
+        let start = Instant::now();
+        let records = context.records(b'\n')?;
+        if context.part == 1 {
+            stream_part1(records, options)?;
+        } else {
+            stream_part2(records, options)?;
+        }
+        let elapsed = humantime::format_duration(start.elapsed());
+
+        println!("  Streaming: {elapsed}");
+        return Ok(());
+    }
 
     let start = Instant::now();
     let parsed = parsing(&context.input)?;
@@ -94,9 +189,9 @@ pub fn main() -> color_eyre::Result<()> {
 
     let start = Instant::now();
     if context.part == 1 {
-        part1(parsed);
+        part1(parsed, options)?;
     } else {
-        part2(parsed);
+        part2(parsed, options)?;
     }
     let elapsed_part = humantime::format_duration(start.elapsed());
 
@@ -105,3 +200,21 @@ pub fn main() -> color_eyre::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "0 3 6 9 12 15\n1 3 6 10 15 21\n10 13 16 21 30 45\n";
+
+    #[test]
+    fn steps() {
+        let input = parsing(&EXAMPLE.into()).unwrap();
+        let sum = |steps, backwards| extrapolate_sum(&input, steps, backwards).unwrap();
+
+        assert_eq!(sum(0, false), 81);
+        assert_eq!(sum(0, true), 11);
+        assert_eq!(sum(1, false), 114);
+        assert_eq!(sum(1, true), 2);
+    }
+}
@@ -0,0 +1,15 @@
+use aoc_2023::features;
+
+/// Lists the days this build can run, with the features missing for the others
+pub fn main() {
+    for &day in features::DAYS {
+        match features::unavailable(day) {
+            Some(reason) => println!("{reason}"),
+            None => println!("day{day} is available"),
+        }
+    }
+
+    if !features::enabled("z3") {
+        println!("day24 --z3 is unavailable, build it with `--features z3`");
+    }
+}
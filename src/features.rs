@@ -0,0 +1,106 @@
+//! Optional features of the crate, and the days that can't be built without them
+
+/// Days having a binary, `src/bin/day{N}.rs`
+pub const DAYS: &[u32] = &[
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 22, 23, 24, 25,
+];
+
+/// Features needed by each day, kept in sync with the `required-features` of `Cargo.toml`. Days
+/// that are not listed only need the default dependencies
+pub const DAY_FEATURES: &[(u32, &[&str])] = &[
+    (1, &["aho-corasick"]),
+    (3, &["regex"]),
+    (10, &["petgraph"]),
+    (11, &["bitvec"]),
+    (12, &["im", "indicatif"]),
+    (16, &["petgraph"]),
+    (20, &["bitvec"]),
+    (23, &["petgraph"]),
+    (25, &["petgraph"]),
+];
+
+/// Whether this build of the crate has `feature` enabled
+pub fn enabled(feature: &str) -> bool {
+    match feature {
+        "aho-corasick" => cfg!(feature = "aho-corasick"),
+        "bitvec" => cfg!(feature = "bitvec"),
+        "im" => cfg!(feature = "im"),
+        "indicatif" => cfg!(feature = "indicatif"),
+        "petgraph" => cfg!(feature = "petgraph"),
+        "regex" => cfg!(feature = "regex"),
+        "z3" => cfg!(feature = "z3"),
+        _ => false,
+    }
+}
+
+/// Features `day` needs that are not enabled in this build, empty if it is available
+pub fn missing(day: u32) -> Vec<&'static str> {
+    DAY_FEATURES
+        .iter()
+        .filter(|&&(d, _)| d == day)
+        .flat_map(|&(_, features)| features.iter().copied())
+        .filter(|f| !enabled(f))
+        .collect()
+}
+
+/// Why `day` can't be run in this build, `None` if it can
+pub fn unavailable(day: u32) -> Option<String> {
+    let missing = missing(day);
+    (!missing.is_empty()).then(|| {
+        format!(
+            "day{day} is unavailable, build it with `--features {}`",
+            missing.join(",")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Features required by each `[[bin]]` of the manifest
+    fn manifest_features() -> Vec<(u32, Vec<String>)> {
+        let manifest = include_str!("../Cargo.toml");
+        let mut bins = Vec::new();
+        let mut day = None;
+
+        for line in manifest.lines() {
+            if let Some(name) = line.strip_prefix("name = \"day") {
+                day = name.trim_end_matches('"').parse().ok();
+            } else if let Some(features) = line.strip_prefix("required-features = [") {
+                let features = features
+                    .trim_end_matches(']')
+                    .split(',')
+                    .map(|f| f.trim().trim_matches('"').to_string())
+                    .collect();
+                bins.push((day.take().unwrap(), features));
+            }
+        }
+
+        bins
+    }
+
+    #[test]
+    fn table_matches_manifest() {
+        let table = DAY_FEATURES
+            .iter()
+            .map(|&(day, features)| (day, features.iter().map(|f| f.to_string()).collect()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(table, manifest_features());
+    }
+
+    #[test]
+    fn days_match_binaries() {
+        let bins = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/src/bin")).unwrap();
+        let mut days = bins
+            .filter_map(|entry| {
+                let name = entry.unwrap().file_name().into_string().unwrap();
+                name.strip_prefix("day")?.strip_suffix(".rs")?.parse().ok()
+            })
+            .collect::<Vec<u32>>();
+        days.sort_unstable();
+
+        assert_eq!(days, DAYS);
+    }
+}
@@ -0,0 +1,501 @@
+use std::ops::{Add, Index, IndexMut, Range, Sub};
+
+/// Integer types that can be used as the bounds of an [Interval]
+pub trait Bound: Copy + Ord + Add<Output = Self> + Sub<Output = Self> {
+    const ZERO: Self;
+    const ONE: Self;
+}
+
+macro_rules! impl_bound {
+    ($($t:ty),*) => {
+        $(
+            impl Bound for $t {
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+            }
+        )*
+    };
+}
+
+impl_bound!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// Half open interval `start..end`, empty when `start >= end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval<T> {
+    pub start: T,
+    pub end: T,
+}
+
+impl<T: Bound> Interval<T> {
+    pub fn new(start: T, end: T) -> Self {
+        Self { start, end }
+    }
+
+    pub fn from_len(start: T, len: T) -> Self {
+        Self::new(start, start + len)
+    }
+
+    pub fn inclusive(min: T, max: T) -> Self {
+        Self::new(min, max + T::ONE)
+    }
+
+    pub fn empty() -> Self {
+        Self::new(T::ZERO, T::ZERO)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    pub fn len(&self) -> T {
+        match self.is_empty() {
+            true => T::ZERO,
+            false => self.end - self.start,
+        }
+    }
+
+    pub fn contains(&self, value: T) -> bool {
+        self.start <= value && value < self.end
+    }
+
+    pub fn overlaps(&self, other: &Self) -> bool {
+        !self.intersection(other).is_empty()
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self::new(self.start.max(other.start), self.end.min(other.end))
+    }
+
+    /// Smallest interval containing both, if they overlap or touch
+    pub fn union(&self, other: &Self) -> Option<Self> {
+        if self.is_empty() {
+            return Some(*other);
+        }
+        if other.is_empty() {
+            return Some(*self);
+        }
+
+        match self.start <= other.end && other.start <= self.end {
+            true => Some(Self::new(
+                self.start.min(other.start),
+                self.end.max(other.end),
+            )),
+            false => None,
+        }
+    }
+
+    /// Parts of `self` before and after `other`, both possibly empty
+    pub fn difference(&self, other: &Self) -> (Self, Self) {
+        if other.is_empty() {
+            return (*self, Self::empty());
+        }
+
+        let (before, rest) = self.split_at(other.start);
+        let (_, after) = rest.split_at(other.end);
+        (before, after)
+    }
+
+    /// Splits into the values lower than `at` and the others
+    pub fn split_at(&self, at: T) -> (Self, Self) {
+        let at = at.clamp(self.start, self.end.max(self.start));
+        (Self::new(self.start, at), Self::new(at, self.end))
+    }
+
+    pub fn range(&self) -> Range<T> {
+        self.start..self.end
+    }
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for Interval<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Set of values stored as sorted, disjoint & non adjacent intervals
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct IntervalSet<T> {
+    intervals: Vec<Interval<T>>,
+}
+
+impl<T: Bound> IntervalSet<T> {
+    pub fn new() -> Self {
+        Self {
+            intervals: Vec::new(),
+        }
+    }
+
+    pub fn intervals(&self) -> &[Interval<T>] {
+        &self.intervals
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Interval<T>> + '_ {
+        self.intervals.iter().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Number of values in the set
+    pub fn len(&self) -> T {
+        self.intervals
+            .iter()
+            .fold(T::ZERO, |total, interval| total + interval.len())
+    }
+
+    pub fn min(&self) -> Option<T> {
+        self.intervals.first().map(|i| i.start)
+    }
+
+    pub fn contains(&self, value: T) -> bool {
+        let idx = self.intervals.partition_point(|i| i.end <= value);
+        self.intervals
+            .get(idx)
+            .map(|i| i.contains(value))
+            .unwrap_or(false)
+    }
+
+    pub fn insert(&mut self, mut interval: Interval<T>) {
+        if interval.is_empty() {
+            return;
+        }
+
+        let lo = self.intervals.partition_point(|i| i.end < interval.start);
+        let hi = self.intervals.partition_point(|i| i.start <= interval.end);
+
+        if lo < hi {
+            interval.start = interval.start.min(self.intervals[lo].start);
+            interval.end = interval.end.max(self.intervals[hi - 1].end);
+        }
+
+        self.intervals.splice(lo..hi, [interval]);
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut union = self.clone();
+        other.iter().for_each(|i| union.insert(i));
+        union
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut intersection = Vec::new();
+
+        let (mut a, mut b) = (0, 0);
+        while a < self.intervals.len() && b < other.intervals.len() {
+            let (ia, ib) = (self.intervals[a], other.intervals[b]);
+
+            let common = ia.intersection(&ib);
+            if !common.is_empty() {
+                intersection.push(common);
+            }
+
+            match ia.end < ib.end {
+                true => a += 1,
+                false => b += 1,
+            }
+        }
+
+        Self {
+            intervals: intersection,
+        }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut difference = Vec::new();
+
+        for mut rest in self.iter() {
+            let first = other.intervals.partition_point(|i| i.end <= rest.start);
+            for removed in &other.intervals[first..] {
+                if removed.start >= rest.end {
+                    break;
+                }
+
+                let (before, after) = rest.difference(removed);
+                if !before.is_empty() {
+                    difference.push(before);
+                }
+                rest = after;
+            }
+
+            if !rest.is_empty() {
+                difference.push(rest);
+            }
+        }
+
+        Self {
+            intervals: difference,
+        }
+    }
+}
+
+impl<T: Bound> FromIterator<Interval<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Interval<T>>>(iter: I) -> Self {
+        let mut set = Self::new();
+        iter.into_iter().for_each(|i| set.insert(i));
+        set
+    }
+}
+
+/// Axis aligned box in `N` dimensions, empty when any of its sides is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IntervalBox<T, const N: usize>(pub [Interval<T>; N]);
+
+impl<T: Bound, const N: usize> IntervalBox<T, N> {
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().any(Interval::is_empty)
+    }
+
+    /// Number of points in the box, computed in `V` to avoid overflows
+    pub fn volume<V>(&self) -> V
+    where
+        T: Into<V>,
+        V: std::iter::Product,
+    {
+        self.0.iter().map(|i| i.len().into()).product()
+    }
+
+    pub fn contains(&self, point: &[T; N]) -> bool {
+        self.0.iter().zip(point).all(|(i, &v)| i.contains(v))
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self(std::array::from_fn(|d| self.0[d].intersection(&other.0[d])))
+    }
+
+    /// Disjoint boxes covering the points of `self` that are not in `other`
+    pub fn difference(&self, other: &Self) -> Vec<Self> {
+        let common = self.intersection(other);
+        if self.is_empty() {
+            return Vec::new();
+        }
+        if common.is_empty() {
+            return vec![*self];
+        }
+
+        let mut difference = Vec::new();
+        let mut rest = *self;
+        for d in 0..N {
+            let (before, after) = rest.0[d].difference(&common.0[d]);
+
+            for side in [before, after] {
+                if !side.is_empty() {
+                    let mut piece = rest;
+                    piece.0[d] = side;
+                    difference.push(piece);
+                }
+            }
+
+            rest.0[d] = common.0[d];
+        }
+
+        difference
+    }
+
+    /// Iterates over every point in the box, the last axis varying the fastest
+    pub fn points(&self) -> Points<T, N> {
+        Points {
+            bounds: *self,
+            next: (!self.is_empty()).then(|| self.0.map(|i| i.start)),
+        }
+    }
+}
+
+impl<T, const N: usize> Index<usize> for IntervalBox<T, N> {
+    type Output = Interval<T>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl<T, const N: usize> IndexMut<usize> for IntervalBox<T, N> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.0[index]
+    }
+}
+
+pub struct Points<T, const N: usize> {
+    bounds: IntervalBox<T, N>,
+    next: Option<[T; N]>,
+}
+
+impl<T: Bound, const N: usize> Iterator for Points<T, N> {
+    type Item = [T; N];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next?;
+
+        let mut next = current;
+        self.next = None;
+        for d in (0..N).rev() {
+            next[d] = next[d] + T::ONE;
+            if next[d] < self.bounds.0[d].end {
+                self.next = Some(next);
+                break;
+            }
+            next[d] = self.bounds.0[d].start;
+        }
+
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Values the intervals are drawn from, with some room on each side
+    const VALUES: Range<i32> = -2..22;
+
+    fn interval() -> impl Strategy<Value = Interval<i32>> {
+        (0..20, 0..20).prop_map(|(start, end)| Interval::new(start, end))
+    }
+
+    fn set() -> impl Strategy<Value = Vec<Interval<i32>>> {
+        proptest::collection::vec(interval(), 0..6)
+    }
+
+    fn boxes<const N: usize>() -> impl Strategy<Value = IntervalBox<i32, N>> {
+        proptest::array::uniform::<_, N>(
+            (0..6, 0..6).prop_map(|(start, end)| Interval::new(start, end)),
+        )
+        .prop_map(IntervalBox)
+    }
+
+    fn points(interval: Interval<i32>) -> BTreeSet<i32> {
+        VALUES.filter(|&v| interval.contains(v)).collect()
+    }
+
+    fn set_points(set: &IntervalSet<i32>) -> BTreeSet<i32> {
+        VALUES.filter(|&v| set.contains(v)).collect()
+    }
+
+    fn union_points(intervals: &[Interval<i32>]) -> BTreeSet<i32> {
+        intervals.iter().flat_map(|&i| points(i)).collect()
+    }
+
+    fn box_points<const N: usize>(b: &IntervalBox<i32, N>) -> BTreeSet<[i32; N]> {
+        IntervalBox([Interval::new(-1, 7); N])
+            .points()
+            .filter(|p| b.contains(p))
+            .collect()
+    }
+
+    fn check_coalesced(set: &IntervalSet<i32>) {
+        for i in set.intervals() {
+            assert!(!i.is_empty(), "{set:?}");
+        }
+        for w in set.intervals().windows(2) {
+            assert!(w[0].end < w[1].start, "{set:?}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn interval_intersection(a in interval(), b in interval()) {
+            let expected: BTreeSet<_> = points(a).intersection(&points(b)).copied().collect();
+            prop_assert_eq!(points(a.intersection(&b)), expected.clone());
+            prop_assert_eq!(a.overlaps(&b), !expected.is_empty());
+        }
+
+        #[test]
+        fn interval_union(a in interval(), b in interval()) {
+            let expected: BTreeSet<_> = points(a).union(&points(b)).copied().collect();
+            let contiguous = expected
+                .iter()
+                .zip(expected.iter().skip(1))
+                .all(|(x, y)| x + 1 == *y);
+
+            match a.union(&b) {
+                Some(union) => prop_assert_eq!(points(union), expected),
+                None => prop_assert!(!a.is_empty() && !b.is_empty() && !contiguous),
+            }
+        }
+
+        #[test]
+        fn interval_difference(a in interval(), b in interval()) {
+            let (before, after) = a.difference(&b);
+            let expected: BTreeSet<_> = points(a).difference(&points(b)).copied().collect();
+
+            prop_assert_eq!(union_points(&[before, after]), expected);
+            prop_assert!(points(before).iter().all(|&v| v < b.start || b.is_empty()));
+            prop_assert!(points(after).iter().all(|&v| v >= b.end));
+        }
+
+        #[test]
+        fn interval_split(a in interval(), at in VALUES) {
+            let (low, high) = a.split_at(at);
+            prop_assert_eq!(points(low), points(a).into_iter().filter(|&v| v < at).collect());
+            prop_assert_eq!(points(high), points(a).into_iter().filter(|&v| v >= at).collect());
+            prop_assert_eq!(low.len() + high.len(), a.len());
+        }
+
+        #[test]
+        fn set_coalescing(intervals in set()) {
+            let set: IntervalSet<_> = intervals.iter().copied().collect();
+            check_coalesced(&set);
+
+            let expected = union_points(&intervals);
+            prop_assert_eq!(set_points(&set), expected.clone());
+            prop_assert_eq!(set.len() as usize, expected.len());
+            prop_assert_eq!(set.min(), expected.first().copied());
+        }
+
+        #[test]
+        fn set_operations(a in set(), b in set()) {
+            let (a, b): (IntervalSet<_>, IntervalSet<_>) =
+                (a.into_iter().collect(), b.into_iter().collect());
+            let (pa, pb) = (set_points(&a), set_points(&b));
+
+            let union = a.union(&b);
+            check_coalesced(&union);
+            prop_assert_eq!(set_points(&union), pa.union(&pb).copied().collect());
+
+            let intersection = a.intersection(&b);
+            check_coalesced(&intersection);
+            prop_assert_eq!(
+                set_points(&intersection),
+                pa.intersection(&pb).copied().collect()
+            );
+
+            let difference = a.difference(&b);
+            check_coalesced(&difference);
+            prop_assert_eq!(
+                set_points(&difference),
+                pa.difference(&pb).copied().collect()
+            );
+        }
+
+        #[test]
+        fn box_volume(b in boxes::<3>()) {
+            prop_assert_eq!(b.volume::<i32>() as usize, box_points(&b).len());
+            prop_assert_eq!(b.points().collect::<BTreeSet<_>>(), box_points(&b));
+            prop_assert_eq!(b.is_empty(), box_points(&b).is_empty());
+        }
+
+        #[test]
+        fn box_difference(a in boxes::<3>(), b in boxes::<3>()) {
+            let difference = a.difference(&b);
+            let expected: BTreeSet<_> =
+                box_points(&a).difference(&box_points(&b)).copied().collect();
+
+            let mut covered = BTreeSet::new();
+            for piece in &difference {
+                prop_assert!(!piece.is_empty());
+                for point in box_points(piece) {
+                    prop_assert!(covered.insert(point), "{:?} is covered twice", point);
+                }
+            }
+            prop_assert_eq!(covered, expected);
+            prop_assert_eq!(
+                difference.iter().map(|p| p.volume::<i32>()).sum::<i32>(),
+                a.volume::<i32>() - a.intersection(&b).volume::<i32>()
+            );
+        }
+    }
+}
@@ -1,20 +1,71 @@
-use std::path::PathBuf;
+use std::{num::NonZeroUsize, path::PathBuf};
 
-use bstr::{BString, ByteSlice};
+use bstr::BString;
 use clap::Parser;
 
+pub mod features;
+pub mod interval;
+#[cfg(feature = "petgraph")]
+pub mod maze;
+pub mod parse;
+pub mod stream;
+
 #[derive(Parser)]
-struct Args {
+struct Args<T: clap::Args> {
     #[arg(short, long)]
     part: u32,
     #[arg(short, long)]
     input: PathBuf,
+    #[command(flatten)]
+    extra: T,
+}
+
+/// Options for days that don't take any arguments beyond the part & input
+#[derive(clap::Args, Debug)]
+pub struct NoArgs {}
+
+/// Options for days that can solve their input while reading it
+#[derive(clap::Args, Debug)]
+pub struct Streamable<T: clap::Args = NoArgs> {
+    /// Process the input record by record instead of loading it whole
+    #[arg(long)]
+    pub stream: bool,
+    #[command(flatten)]
+    pub args: T,
+}
+
+/// Options for days that can spread their work over multiple threads
+#[derive(clap::Args, Debug)]
+pub struct Threads {
+    /// Number of threads to use, 1 running everything in order [default: one per CPU]
+    #[arg(long)]
+    pub threads: Option<NonZeroUsize>,
+}
+
+impl Threads {
+    /// Runs `f` in a thread pool of the requested size, parallel iterators in `f` using it
+    pub fn install<R: Send>(&self, f: impl FnOnce() -> R + Send) -> color_eyre::Result<R> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads.map_or(0, NonZeroUsize::get))
+            .build()?;
+        Ok(pool.install(f))
+    }
 }
 
 #[derive(Debug)]
-pub struct Context {
+pub struct Context<T = NoArgs> {
     pub part: u32,
+    /// Content of the input, empty when streaming it
     pub input: BString,
+    pub path: PathBuf,
+    pub args: T,
+}
+
+impl<T: clap::Args> Context<Streamable<T>> {
+    /// Reads the input by records ending with `separator`
+    pub fn records(&self, separator: u8) -> color_eyre::Result<stream::Records> {
+        stream::Records::open(&self.path, separator)
+    }
 }
 
 #[macro_export]
@@ -35,27 +86,46 @@ macro_rules! print_res_part {
     };
 }
 
-pub fn parse_u64_bytes(b: &[u8]) -> u64 {
-    b.iter()
-        .map(|d| {
-            if d.is_ascii_digit() {
-                (d - b'0') as u64
-            } else {
-                panic!("Invalid digit in {}", b.as_bstr())
-            }
-        })
-        .fold(0, |acc, d| acc * 10 + d)
+/// Parses a decimal number, `None` if `b` is not made only of digits or overflows
+pub fn parse_u64_bytes(b: &[u8]) -> Option<u64> {
+    if b.is_empty() {
+        return None;
+    }
+
+    b.iter().try_fold(0u64, |acc, d| {
+        d.is_ascii_digit()
+            .then(|| acc.checked_mul(10)?.checked_add((d - b'0') as u64))?
+    })
 }
 
 pub fn load() -> color_eyre::Result<Context> {
+    load_with()
+}
+
+/// Same as [load], with day specific options
+pub fn load_with<T: clap::Args>() -> color_eyre::Result<Context<T>> {
+    load_args(|_| true)
+}
+
+/// Same as [load_with], the input being only read if it is not streamed
+pub fn load_streamable<T: clap::Args>() -> color_eyre::Result<Context<Streamable<T>>> {
+    load_args(|args: &Streamable<T>| !args.stream)
+}
+
+fn load_args<T: clap::Args>(read: impl FnOnce(&T) -> bool) -> color_eyre::Result<Context<T>> {
     color_eyre::install()?;
 
-    let args = Args::parse();
+    let args = Args::<T>::parse();
 
-    let input = std::fs::read(args.input)?.into();
+    let input = match read(&args.extra) {
+        true => std::fs::read(&args.input)?.into(),
+        false => BString::default(),
+    };
 
     Ok(Context {
         part: args.part,
         input,
+        path: args.input,
+        args: args.extra,
     })
 }
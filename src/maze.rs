@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use petgraph::prelude::*;
+
+pub type Point = (usize, usize);
+
+/// Grid maze collapsed to its junctions, the corridors between them becoming edges weighted by
+/// their length
+#[derive(Debug, Clone)]
+pub struct Junctions {
+    /// Junctions are identified by their index in [points](Self::points)
+    pub graph: DiGraphMap<usize, usize>,
+    pub points: Vec<Point>,
+}
+
+/// Cells next to `(x, y)` in a `width` by `height` grid
+fn neighbours((x, y): Point, width: usize, height: usize) -> impl Iterator<Item = Point> {
+    [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ]
+    .into_iter()
+    .filter(move |&(x, y)| x < width && y < height)
+}
+
+impl Junctions {
+    /// Collapses a `width` by `height` grid, `step(from, to)` telling if the neighbouring cell `to`
+    /// can be entered from `from`.
+    ///
+    /// Junctions are the cells connected in any direction to at least three neighbours, along with
+    /// the `extra` cells (such as the entrances of the maze) that come first in the points.
+    /// Corridors leading to a dead end are dropped, and only the longest corridor between two
+    /// junctions is kept.
+    pub fn new(
+        width: usize,
+        height: usize,
+        extra: &[Point],
+        step: impl Fn(Point, Point) -> bool,
+    ) -> Self {
+        let connected = |a, b| step(a, b) || step(b, a);
+
+        let mut points = extra.to_vec();
+        for y in 0..height {
+            for x in 0..width {
+                let connections = neighbours((x, y), width, height)
+                    .filter(|&n| connected((x, y), n))
+                    .count();
+                if connections >= 3 && !extra.contains(&(x, y)) {
+                    points.push((x, y));
+                }
+            }
+        }
+
+        let index: HashMap<_, _> = points.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+
+        let mut graph = DiGraphMap::new();
+        for i in 0..points.len() {
+            graph.add_node(i);
+        }
+
+        for (junction, &point) in points.iter().enumerate() {
+            'corridors: for mut current in neighbours(point, width, height) {
+                if !step(point, current) {
+                    continue;
+                }
+
+                let mut from = point;
+                let mut len = 1;
+                while !index.contains_key(&current) {
+                    let Some(next) = neighbours(current, width, height)
+                        .find(|&next| next != from && step(current, next))
+                    else {
+                        continue 'corridors;
+                    };
+                    from = current;
+                    current = next;
+                    len += 1;
+                }
+
+                let end = index[&current];
+                let len = graph
+                    .edge_weight(junction, end)
+                    .map_or(len, |&l| std::cmp::max(l, len));
+                graph.add_edge(junction, end, len);
+            }
+        }
+
+        Self { graph, points }
+    }
+}
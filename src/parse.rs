@@ -0,0 +1,170 @@
+use std::{fmt::Display, str::FromStr};
+
+use bstr::{BString, ByteSlice};
+
+/// Error in the input, pointing at the bytes that could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    message: String,
+    /// Line of the error, starting at 1
+    pub line: usize,
+    /// Column of the first invalid byte, starting at 1
+    pub column: usize,
+    /// Number of invalid bytes, at least one
+    len: usize,
+    /// Whole line containing the error
+    snippet: BString,
+    /// Other errors found in the same input, reported after this one
+    others: Vec<ParseError>,
+}
+
+impl ParseError {
+    /// Error on `span`, which is a slice of `input`. A `span` that is not part of `input` points at
+    /// the end of the input, which is the case of some empty slices (see [split])
+    pub fn at(input: &[u8], span: &[u8], message: impl Display) -> Self {
+        let offset = (span.as_ptr() as usize).wrapping_sub(input.as_ptr() as usize);
+        match offset.checked_add(span.len()) {
+            Some(end) if end <= input.len() => Self::at_offset(input, offset, span.len(), message),
+            _ => Self::at_offset(input, input.len(), 0, message),
+        }
+    }
+
+    /// Error on the `len` bytes of `input` starting at `offset`
+    pub fn at_offset(input: &[u8], offset: usize, len: usize, message: impl Display) -> Self {
+        let start = input[..offset].rfind_byte(b'\n').map_or(0, |i| i + 1);
+        let end = input[offset..]
+            .find_byte(b'\n')
+            .map_or(input.len(), |i| offset + i);
+
+        Self {
+            message: message.to_string(),
+            line: input[..start].find_iter(b"\n").count() + 1,
+            column: offset - start + 1,
+            // The carets stop at the end of the line, but always show where the error is
+            len: std::cmp::max(std::cmp::min(len, end - offset), 1),
+            snippet: input[start..end].trim_end_with(|c| c == '\r').into(),
+            others: Vec::new(),
+        }
+    }
+
+    /// Moves an error located in a record holding a single line to the line it was read from
+    pub fn on_line(mut self, line: usize) -> Self {
+        self.line = line;
+        self
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gutter = self.line.to_string().len();
+
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "{:gutter$}--> {}:{}", "", self.line, self.column)?;
+        writeln!(f, "{:gutter$} |", "")?;
+        writeln!(f, "{} | {}", self.line, self.snippet)?;
+        write!(
+            f,
+            "{:gutter$} | {:pad$}{}",
+            "",
+            "",
+            "^".repeat(self.len),
+            pad = self.column - 1
+        )?;
+
+        for other in &self.others {
+            write!(f, "\n\n{other}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Fails with all the `errors`, in the order they appear in the input, if there are any
+pub fn all(mut errors: Vec<ParseError>) -> Result<(), ParseError> {
+    errors.sort_by_key(|e| (e.line, e.column));
+
+    let mut errors = errors.into_iter();
+    match errors.next() {
+        None => Ok(()),
+        Some(mut first) => {
+            first.others.extend(errors);
+            Err(first)
+        }
+    }
+}
+
+/// Parses `span`, a slice of `input`, with [FromStr]
+pub fn from_str<T>(input: &[u8], span: &[u8]) -> Result<T, ParseError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    span.to_str()
+        .map_err(|e| ParseError::at(input, span, e))?
+        .parse()
+        .map_err(|e| ParseError::at(input, span, e))
+}
+
+/// Splits `haystack` on `separator` like [ByteSlice::split_str], but every field is a slice of
+/// `haystack`, even empty ones, so that errors on them point at where they are
+pub fn split<'a>(haystack: &'a [u8], separator: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+    let mut start = 0;
+    haystack
+        .find_iter(separator)
+        .chain([haystack.len()])
+        .map(move |end| {
+            let field = &haystack[start..end];
+            start = end + separator.len();
+            field
+        })
+}
+
+/// Views `input` as UTF-8, pointing at the first invalid byte otherwise
+pub fn utf8(input: &[u8]) -> Result<&str, ParseError> {
+    std::str::from_utf8(input).map_err(|e| {
+        let len = e.error_len().unwrap_or(input.len() - e.valid_up_to());
+        ParseError::at_offset(input, e.valid_up_to(), len, e)
+    })
+}
+
+/// Parses a grid with a cell per byte, all the lines having the same width. `cell` is `None` for
+/// invalid cells
+pub fn grid<T, R: FromIterator<T>>(
+    input: &[u8],
+    cell: impl Fn(u8) -> Option<T>,
+) -> Result<Vec<R>, ParseError> {
+    grid_in(input, input, cell)
+}
+
+/// Same as [grid], for a grid that is only a slice of `input`
+pub fn grid_in<T, R: FromIterator<T>>(
+    input: &[u8],
+    grid: &[u8],
+    cell: impl Fn(u8) -> Option<T>,
+) -> Result<Vec<R>, ParseError> {
+    let width = grid.lines().next().map_or(0, |l| l.len());
+    if width == 0 {
+        return Err(ParseError::at(input, grid, "Empty grid"));
+    }
+
+    grid.lines()
+        .map(|line| {
+            if line.len() != width {
+                return Err(ParseError::at(
+                    input,
+                    line,
+                    format!("Line is not {width} cells long"),
+                ));
+            }
+
+            line.iter()
+                .enumerate()
+                .map(|(x, &c)| {
+                    cell(c).ok_or_else(|| ParseError::at(input, &line[x..=x], "Invalid cell"))
+                })
+                .collect()
+        })
+        .collect()
+}
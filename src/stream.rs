@@ -0,0 +1,102 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    iter::Sum,
+    path::Path,
+};
+
+use bstr::{BStr, ByteSlice};
+use rayon::prelude::*;
+
+/// Number of records processed at once in [Records::par_sum]
+const BATCH_SIZE: usize = 4096;
+
+/// Input read one record at a time, records being delimited by a separator
+pub struct Records {
+    reader: BufReader<File>,
+    separator: u8,
+    index: usize,
+}
+
+impl Records {
+    pub fn open(path: &Path, separator: u8) -> color_eyre::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(File::open(path)?),
+            separator,
+            index: 0,
+        })
+    }
+
+    /// Reads the next record in `buffer` (without its separator or line ending), returning its
+    /// index
+    fn next_into(&mut self, buffer: &mut Vec<u8>) -> color_eyre::Result<Option<usize>> {
+        buffer.clear();
+        if self.reader.read_until(self.separator, buffer)? == 0 {
+            return Ok(None);
+        }
+
+        while let Some(b'\n' | b'\r') = buffer.last() {
+            buffer.pop();
+        }
+        if buffer.last() == Some(&self.separator) {
+            buffer.pop();
+        }
+
+        // A separator ending the input does not start a new record
+        if buffer.is_empty() && self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+
+        let index = self.index;
+        self.index += 1;
+        Ok(Some(index))
+    }
+
+    /// Calls `f` on each record with its index, in order
+    pub fn try_for_each(
+        mut self,
+        mut f: impl FnMut(usize, &BStr) -> color_eyre::Result<()>,
+    ) -> color_eyre::Result<()> {
+        let mut buffer = Vec::new();
+        while let Some(index) = self.next_into(&mut buffer)? {
+            f(index, buffer.as_bstr())?;
+        }
+
+        Ok(())
+    }
+
+    /// Sums `f` over the records, computing it in parallel over batches of records
+    pub fn par_sum<S>(
+        mut self,
+        f: impl Fn(usize, &BStr) -> color_eyre::Result<S> + Sync,
+    ) -> color_eyre::Result<S>
+    where
+        S: Sum + Send,
+    {
+        let mut total = S::sum(std::iter::empty());
+        let mut batch = vec![(0, Vec::new()); BATCH_SIZE];
+
+        loop {
+            let mut len = 0;
+            for (index, buffer) in &mut batch {
+                match self.next_into(buffer)? {
+                    Some(i) => *index = i,
+                    None => break,
+                }
+                len += 1;
+            }
+
+            let batch_total = batch[..len]
+                .par_iter()
+                .map(|(index, record)| f(*index, record.as_bstr()))
+                .collect::<color_eyre::Result<Vec<S>>>()?
+                .into_iter()
+                .sum::<S>();
+            total = [total, batch_total].into_iter().sum();
+
+            if len < batch.len() {
+                break Ok(total);
+            }
+        }
+    }
+}